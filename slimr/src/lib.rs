@@ -1,60 +1,160 @@
 #![allow(dead_code)] // TODO remove
 
-use std::collections::BTreeMap;
+use std::{fmt, io};
 
-use slimr_shared::renderer;
+use slimr_shared::write::IoWriter;
+use slimr_shared::{interpreter, renderer};
 
 pub use slimr_derive::SlimR;
+pub use slimr_shared::context::{Context, Value};
+pub use slimr_shared::escape;
+pub use slimr_shared::filters;
+pub use slimr_shared::parser::ast::{ParseError, ParseErrorKind};
+pub use slimr_shared::parser::indent::Indent;
+pub use slimr_shared::renderer::{Dialect, IoError, RenderError, RenderOptions};
+pub use slimr_shared::template_manager::TemplateManager;
 
 //Trait for rendering the template. It's usually derived.
 pub trait SlimR {
-    fn render(&self) -> String;
-}
+    ///Writes the html into `w` without building it in a `String` first.
+    fn render_into(&self, w: &mut impl fmt::Write) -> fmt::Result;
+
+    ///Panics if a value can't be formatted, like a value that the `json`
+    ///filter can't serialize. `try_render` returns that error instead.
+    fn render(&self) -> String {
+        self.try_render()
+            .expect("a value of the template couldn't be formatted")
+    }
 
-pub fn render_template_str(input: &str, values: &BTreeMap<&str, &str>) -> String {
-    let mut html = render_static_template_str(input);
-    for (k, v) in values {
-        let search = format!("{{{{{}}}}}", k);
-        html = html.replace(&search, v);
+    fn try_render(&self) -> Result<String, fmt::Error> {
+        let mut html = String::new();
+        self.render_into(&mut html)?;
+        Ok(html)
     }
-    html
+
+    ///Writes the html into a file, a socket or any other `io::Write`.
+    fn render_into_io(&self, w: &mut impl io::Write) -> io::Result<()> {
+        let mut writer = IoWriter::new(w);
+        let result = self.render_into(&mut writer);
+        writer.finish(result)
+    }
+}
+
+///The position of the current item in a `- for` loop.
+///It's available as `loop` in the body of the loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Loop {
+    ///Starts at 1
+    pub index: usize,
+    ///Starts at 0
+    pub index0: usize,
+    pub first: bool,
+    pub last: bool,
 }
 
-pub fn render_static_template_str(input: &str) -> String {
-    renderer::render_source(input)
+///Renders the template and fills in its variables from the context.
+///The values are escaped, except for raw variables like `{{{ html }}}`.
+pub fn render_template_str(
+    input: &str,
+    context: impl Into<Context>,
+) -> Result<String, RenderError> {
+    let output = renderer::compile_source(input, &RenderOptions::default())?;
+    interpreter::render(&output, &context.into())
+}
+
+///Renders the template at `path` at runtime and fills in its variables
+///from the context. Templates and their includes are looked up in the
+///template manager first and then on disk.
+pub fn render_template(
+    manager: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+    context: impl Into<Context>,
+) -> Result<String, RenderError> {
+    let output = renderer::compile_template(manager, path, options)?;
+    interpreter::render(&output, &context.into())
+}
+
+///Like `render_template`, but writes the html into `w`. The template is
+///compiled in memory, and its parts are written while the variables are
+///filled in, so a missing variable can leave part of the html written.
+pub fn render_template_into(
+    manager: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+    context: impl Into<Context>,
+    w: &mut impl fmt::Write,
+) -> Result<(), RenderError> {
+    let output = renderer::compile_template(manager, path, options)?;
+    interpreter::render_into(&output, &context.into(), w)
+}
+
+///Like `render_template_into`, but writes into a file, a socket or any
+///other `io::Write`.
+pub fn render_template_into_io(
+    manager: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+    context: impl Into<Context>,
+    w: &mut impl io::Write,
+) -> Result<(), RenderError> {
+    let output = renderer::compile_template(manager, path, options)?;
+    interpreter::render_into_io(&output, &context.into(), w)
+}
+
+pub fn render_static_template_str(input: &str) -> Result<String, RenderError> {
+    render_static_template_str_with(input, &RenderOptions::default())
+}
+
+pub fn render_static_template_str_with(
+    input: &str,
+    options: &RenderOptions,
+) -> Result<String, RenderError> {
+    renderer::render_source(input, options)
+}
+
+///Renders the template at `path`. Templates and their includes are
+///looked up in the template manager first and then on disk.
+pub fn render_static_template(
+    manager: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<String, RenderError> {
+    renderer::render_template(manager, path, options)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
 
     #[test]
     fn renders_single_tag_template() {
-        let output = render_static_template_str("hello");
-        assert_eq!(output, "<hello />")
+        let output = render_static_template_str("hello").unwrap();
+        assert_eq!(output, "<hello></hello>")
     }
 
     #[test]
     fn renders_two_tags_template() {
-        let output = render_static_template_str("br\nbr");
-        assert_eq!(output, "<br /><br />")
+        let output = render_static_template_str("br\nbr").unwrap();
+        assert_eq!(output, "<br><br>")
     }
 
     #[test]
     fn renders_tag_content_template() {
-        let output = render_static_template_str("p hello world");
+        let output = render_static_template_str("p hello world").unwrap();
         assert_eq!(output, "<p>hello world</p>")
     }
 
     #[test]
     fn renders_tag_block_content_template() {
-        let output = render_static_template_str("p:\n  how\n  are\n  you?");
+        let output = render_static_template_str("p:\n  how\n  are\n  you?").unwrap();
         assert_eq!(output, "<p>how<br>are<br>you?</p>")
     }
 
     #[test]
     fn renders_tag_tag_id_attributes_content() {
-        let output = render_static_template_str("div#title.big lang=en How are you?");
+        let output = render_static_template_str("div#title.big lang=en How are you?").unwrap();
         assert_eq!(
             output,
             "<div id=\"title\" class=\"big\" lang=\"en\">How are you?</div>"
@@ -67,7 +167,7 @@ mod tests {
   p#question.informal lang=en:
     How are you, mate?";
         println!("{}", input);
-        let output = render_static_template_str(input);
+        let output = render_static_template_str(input).unwrap();
         let expected = "<div id=\"greeting\" class=\"fancy\" type=\"Friend's Hello\">\
                           <p id=\"question\" class=\"informal\" lang=\"en\">How are you, mate?</p>\
                           </div>";
@@ -79,10 +179,10 @@ mod tests {
         let input = "h1#title.fancy.large Hello World
 div
   img#title-image src=\"images/title.jpg\" width=1000 height=300 alt=\"A great title image.\"";
-        let output = render_static_template_str(input);
+        let output = render_static_template_str(input).unwrap();
         let expected = "<h1 id=\"title\" class=\"fancy large\">Hello World</h1>\
                         <div>\
-                        <img id=\"title-image\" src=\"images/title.jpg\" width=\"1000\" height=\"300\" alt=\"A great title image.\" />\
+                        <img id=\"title-image\" src=\"images/title.jpg\" width=\"1000\" height=\"300\" alt=\"A great title image.\">\
                         </div>";
         assert_eq!(output, expected)
     }
@@ -92,7 +192,132 @@ div
         let mut map = BTreeMap::new();
         map.insert("first_name", "John");
         map.insert("last_name", "Smith");
-        let rendered = render_template_str("h1 Hello, {{first_name}} {{last_name}}!", &map);
+        let rendered =
+            render_template_str("h1 Hello, {{first_name}} {{last_name}}!", &map).unwrap();
         assert_eq!(rendered, "<h1>Hello, John Smith!</h1>")
     }
+
+    #[test]
+    fn escapes_variables() {
+        let mut map = BTreeMap::new();
+        map.insert("name", "<script>alert('hi')</script>");
+        map.insert("link", "\" onclick=\"evil()");
+        map.insert("html", "<b>bold</b>");
+        let rendered =
+            render_template_str("a href=\"/users/{{link}}\" {{name}} {{{ html }}}", &map).unwrap();
+        assert_eq!(
+            rendered,
+            "<a href=\"/users/&quot; onclick=&quot;evil()\">\
+             &lt;script&gt;alert('hi')&lt;/script&gt; <b>bold</b></a>"
+        )
+    }
+
+    #[test]
+    fn renders_context_values() {
+        let context = Context::new()
+            .with("user", "Trinity")
+            .with("unread", 3)
+            .with("admin", false);
+        let rendered = render_template_str(
+            "p {{user}} has {{unread}} new messages. Admin: {{admin}}",
+            context,
+        )
+        .unwrap();
+        assert_eq!(rendered, "<p>Trinity has 3 new messages. Admin: false</p>");
+        let err = render_template_str("p {{user}}", Context::new()).unwrap_err();
+        assert_eq!(err, RenderError::MissingVar("user".to_string()));
+    }
+
+    #[test]
+    fn renders_runtime_templates() {
+        let mut manager = TemplateManager::default();
+        manager.add("page".to_string(), "div\n  >greeting".to_string());
+        manager.add("greeting".to_string(), "p Hi {{name}}".to_string());
+        let context = Context::new().with("name", "Morpheus");
+        let output =
+            render_template(&mut manager, "page", &RenderOptions::default(), context).unwrap();
+        assert_eq!(output, "<div><p>Hi Morpheus</p></div>")
+    }
+
+    #[test]
+    fn renders_runtime_templates_into_writers() {
+        let mut manager = TemplateManager::default();
+        manager.add("greeting".to_string(), "p Hi {{name}}".to_string());
+        let options = RenderOptions::default();
+        let context = Context::new().with("name", "Morpheus");
+        let mut html = String::new();
+        render_template_into(
+            &mut manager,
+            "greeting",
+            &options,
+            context.clone(),
+            &mut html,
+        )
+        .unwrap();
+        assert_eq!(html, "<p>Hi Morpheus</p>");
+        let mut bytes = Vec::new();
+        render_template_into_io(
+            &mut manager,
+            "greeting",
+            &options,
+            context.clone(),
+            &mut bytes,
+        )
+        .unwrap();
+        assert_eq!(bytes, b"<p>Hi Morpheus</p>");
+        let mut buf = [0u8; 4];
+        let err = render_template_into_io(
+            &mut manager,
+            "greeting",
+            &options,
+            context,
+            &mut &mut buf[..],
+        )
+        .unwrap_err();
+        assert!(matches!(err, RenderError::Io(e) if e.0.kind() == io::ErrorKind::WriteZero));
+    }
+
+    struct Greeting;
+
+    impl SlimR for Greeting {
+        fn render_into(&self, w: &mut impl fmt::Write) -> fmt::Result {
+            w.write_str("<p>Hi</p>")
+        }
+    }
+
+    #[test]
+    fn renders_trait_into_writers() {
+        assert_eq!(Greeting.render(), "<p>Hi</p>");
+        assert_eq!(Greeting.try_render(), Ok("<p>Hi</p>".to_string()));
+        let mut bytes = Vec::new();
+        Greeting.render_into_io(&mut bytes).unwrap();
+        assert_eq!(bytes, b"<p>Hi</p>");
+    }
+
+    #[test]
+    fn returns_parse_errors() {
+        let err = render_static_template_str("div\n  p#").unwrap_err();
+        match err {
+            RenderError::Parse(e) => assert_eq!((e.line, e.col), (2, 5)),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn renders_includes_from_manager() {
+        let mut manager = TemplateManager::default();
+        manager.add("page".to_string(), "div\n  >footer".to_string());
+        manager.add("footer".to_string(), "p bye".to_string());
+        let output =
+            render_static_template(&mut manager, "page", &RenderOptions::default()).unwrap();
+        assert_eq!(output, "<div><p>bye</p></div>")
+    }
+
+    #[test]
+    fn renders_pretty_template() {
+        let output =
+            render_static_template_str_with("ul\n  li one\n  li two", &RenderOptions::pretty())
+                .unwrap();
+        assert_eq!(output, "<ul>\n  <li>one</li>\n  <li>two</li>\n</ul>")
+    }
 }
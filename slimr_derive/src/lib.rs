@@ -1,195 +1,886 @@
-use darling::FromDeriveInput;
-use proc_macro2::TokenStream;
-use quote::quote;
-use slimr_shared::renderer;
-use std::iter::Peekable;
-use syn;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+use darling::FromAttributes;
+use proc_macro2::{Group, Literal, Span, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use slimr_shared::context::Value;
+use slimr_shared::escape::Escape;
+use slimr_shared::filters;
+use slimr_shared::output::{Output, Part};
+use slimr_shared::parser::expr;
+use slimr_shared::renderer::{self, RenderOptions};
+use syn::ext::IdentExt;
+use syn::parse::{ParseStream, Parser};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::visit_mut::{self, VisitMut};
 
 #[proc_macro_derive(SlimR, attributes(template))]
 pub fn derive_template(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = syn::parse_macro_input!(input as syn::DeriveInput);
-    let opts = Opts::from_derive_input(&input).expect("Wrong Options");
-    let name = input.ident;
-    let generics = input.generics;
-    let fields = get_named_fields(&input.data);
-    let args = gen_format_args(fields);
+    derive(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    let mut html = match opts {
-        Opts {
-            path: Some(..),
-            source: Some(..),
-        } => panic!("Please only provide one template source"),
-        Opts {
-            path: Some(path),
-            source: _,
-        } => renderer::render_path(&path),
-        Opts {
-            path: _,
-            source: Some(source),
-        } => renderer::render_source(&source),
-        _ => panic!("Please provide either a path or template source code"),
+///Structs have one template. For enums, every variant has its own
+///template and the variant decides which one is rendered.
+fn derive(input: &syn::DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let (body, capacity, warnings) = match &input.data {
+        syn::Data::Struct(data) => {
+            let template = compile(&input.attrs, name, &data.fields, |member, _| {
+                syn::parse_quote! { self.#member }
+            })?;
+            let capacity = template.capacity;
+            (template.body, quote! { #capacity }, template.warnings)
+        }
+        syn::Data::Enum(data) if !data.variants.is_empty() => {
+            let mut arms = Vec::new();
+            let mut capacities = Vec::new();
+            let mut warnings = Vec::new();
+            for variant in &data.variants {
+                //Fields are bound by reference, with a prefix so they don't hide `out`
+                let binding = |name: &str| quote::format_ident!("__slimr_{}", name);
+                let template = compile(
+                    &variant.attrs,
+                    &variant.ident,
+                    &variant.fields,
+                    |_, name| {
+                        let binding = binding(name);
+                        syn::parse_quote! { (*#binding) }
+                    },
+                )?;
+                let ident = &variant.ident;
+                let bound = variant.fields.members().filter_map(|member| {
+                    let name = member_name(&member);
+                    let binding = binding(&name);
+                    template
+                        .used
+                        .contains(&name)
+                        .then(|| quote! { #member: #binding, })
+                });
+                let body = &template.body;
+                let capacity = template.capacity;
+                arms.push(quote! { Self::#ident { #( #bound )* .. } => { #body } });
+                capacities.push(quote! { Self::#ident { .. } => #capacity, });
+                warnings.extend(template.warnings);
+            }
+            let body = quote! {
+                match self {
+                    #( #arms )*
+                }
+            };
+            let capacity = quote! {
+                match self {
+                    #( #capacities )*
+                }
+            };
+            (body, capacity, warnings)
+        }
+        syn::Data::Enum(_) => {
+            let message = "SlimR can't be derived for enums without variants";
+            return Err(syn::Error::new(name.span(), message));
+        }
+        syn::Data::Union(_) => {
+            let message = "SlimR can only be derived for structs and enums";
+            return Err(syn::Error::new(name.span(), message));
+        }
     };
 
-    html = reformat_braces(&html);
+    Ok(quote! {
+        #( #warnings )*
 
-    let tokens = quote! {
-        impl #generics SlimR for #name #generics {
-            fn render(&self) -> String {
-                format!(#html, #args)
+        impl #impl_generics SlimR for #name #ty_generics #where_clause {
+            fn render_into(&self, out: &mut impl ::std::fmt::Write) -> ::std::fmt::Result {
+                #body
+                Ok(())
+            }
+
+            fn try_render(&self) -> ::std::result::Result<String, ::std::fmt::Error> {
+                let mut html = String::with_capacity(#capacity);
+                self.render_into(&mut html)?;
+                Ok(html)
             }
         }
-    };
-    tokens.into()
+    })
 }
 
-#[derive(FromDeriveInput, Default)]
+#[derive(FromAttributes, Default)]
 #[darling(default, attributes(template))]
 struct Opts {
     path: Option<String>,
     source: Option<String>,
+    pretty: bool,
+    indent: Option<String>,
+    dialect: Option<String>,
+    ///The indentation unit of the template source
+    parse_indent: Option<String>,
+    ///Warns about fields that the template doesn't use
+    warn_unused: bool,
 }
 
-fn get_named_fields(data: &syn::Data) -> &syn::FieldsNamed {
-    match *data {
-        syn::Data::Struct(ref data) => match data.fields {
-            syn::Fields::Named(ref fields) => fields,
-            _ => unimplemented!(),
-        },
-        _ => unimplemented!(),
+impl Opts {
+    fn render_options(&self, attr: &TokenStream) -> syn::Result<RenderOptions> {
+        let mut options = RenderOptions {
+            pretty: self.pretty,
+            ..RenderOptions::default()
+        };
+        if let Some(indent) = &self.indent {
+            options.indent = indent.clone();
+        }
+        if let Some(dialect) = &self.dialect {
+            options.dialect = dialect
+                .parse()
+                .map_err(|e| syn::Error::new_spanned(attr, e))?;
+        }
+        if let Some(indent) = &self.parse_indent {
+            options.parse_indent = indent
+                .parse()
+                .map_err(|e| syn::Error::new_spanned(attr, e))?;
+        }
+        Ok(options)
     }
 }
 
-fn gen_format_args(fields: &syn::FieldsNamed) -> TokenStream {
-    let recurse = fields.named.iter().map(|f| {
-        let name = &f.ident;
-        quote! {
-            #name = self.#name
+///The generated code of one `#[template(...)]`.
+struct Template {
+    body: TokenStream,
+    ///The length of the static html
+    capacity: usize,
+    ///The names of the fields that the template uses, like `name` or `0`
+    used: BTreeSet<String>,
+    warnings: Vec<TokenStream>,
+}
+
+///Compiles the template of a struct or a variant. `access` turns a field
+///and its name into the expression that reads it. Errors point at the
+///`#[template(...)]` attribute, or at `ident` if there's none.
+fn compile(
+    attrs: &[syn::Attribute],
+    ident: &syn::Ident,
+    fields: &syn::Fields,
+    access: impl Fn(&syn::Member, &str) -> syn::Expr,
+) -> syn::Result<Template> {
+    let attr = attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("template"))
+        .map_or(ident.to_token_stream(), ToTokens::to_token_stream);
+    let opts = Opts::from_attributes(attrs)?;
+    let options = opts.render_options(&attr)?;
+    let rendered = match opts {
+        Opts {
+            path: Some(..),
+            source: Some(..),
+            ..
+        } => {
+            let message = "Please only provide one template source, either `path` or `source`";
+            return Err(syn::Error::new_spanned(&attr, message));
         }
-    });
-    quote! { #( #recurse ),* }
+        Opts {
+            path: Some(ref path),
+            ..
+        } => renderer::compile_rust_path(path, &options),
+        Opts {
+            source: Some(ref source),
+            ..
+        } => renderer::compile_rust_source(source, &options),
+        _ => {
+            let message = "Please provide either a path or template source code, like \
+                           `#[template(path = \"templates/page.slimr\")]`";
+            return Err(syn::Error::new_spanned(&attr, message));
+        }
+    };
+    let output = rendered.map_err(|e| syn::Error::new_spanned(&attr, e))?;
+    let mut generator = Generator {
+        fields: fields
+            .members()
+            .map(|member| {
+                let name = member_name(&member);
+                let expr = access(&member, &name);
+                (name, expr)
+            })
+            .collect(),
+        locals: Vec::new(),
+        used: BTreeSet::new(),
+        attr,
+    };
+    let body = generator.gen_body(&output)?;
+    let warnings = fields
+        .iter()
+        .zip(fields.members())
+        .filter(|(_, member)| opts.warn_unused && !generator.used.contains(&member_name(member)))
+        .map(|(field, member)| gen_unused_warning(field, &member_name(&member)))
+        .collect();
+    Ok(Template {
+        body,
+        capacity: output.static_len(),
+        used: generator.used,
+        warnings,
+    })
 }
 
-///Turn slimr variables into format! variables
-///and escape all other curly braces.
-fn reformat_braces(source: &str) -> String {
-    //TODO find better way to handle variables and allow single {} in content
-    let mut res = String::with_capacity(source.len());
-    let mut iter = source.chars().peekable();
-    while let Some(c) = iter.next() {
-        match c {
-            '{' => res.push_str(&classify_brace("{".to_string(), &mut iter)),
-            '}' => res.push_str(&classify_brace("}".to_string(), &mut iter)),
-            _ => res.push(c),
+///The name of a field in the template, like `name` or `0` for tuple structs.
+fn member_name(member: &syn::Member) -> String {
+    match member {
+        syn::Member::Named(ident) => ident.unraw().to_string(),
+        syn::Member::Unnamed(index) => index.index.to_string(),
+    }
+}
+
+///Generates the code of the render function.
+struct Generator {
+    ///The fields that templates use like variables, and the expressions that read them
+    fields: BTreeMap<String, syn::Expr>,
+    ///The variables bound by the loops and patterns around the current part
+    locals: Vec<String>,
+    ///The fields that the template uses
+    used: BTreeSet<String>,
+    ///The `#[template(...)]` attribute, where errors in the template are reported
+    attr: TokenStream,
+}
+
+impl Generator {
+    ///Writes the static html and the escaped variables into `out`.
+    fn gen_body(&mut self, output: &Output) -> syn::Result<TokenStream> {
+        let mut tokens = TokenStream::new();
+        for part in output.parts() {
+            tokens.extend(match part {
+                Part::Static(html) => quote! {
+                    ::std::fmt::Write::write_str(out, #html)?;
+                },
+                Part::Var { name, escape, .. } => {
+                    let value = self.gen_var(name)?;
+                    let escape = gen_escape(*escape);
+                    quote! {
+                        ::slimr::escape::write_escaped(out, #escape, &#value)?;
+                    }
+                }
+                Part::If {
+                    branches,
+                    otherwise,
+                } => {
+                    let mut chain = TokenStream::new();
+                    for branch in branches {
+                        let (cond, pattern) = self.gen_cond(&branch.cond)?;
+                        let body =
+                            self.with_bindings(pattern.as_ref(), |gen| gen.gen_body(&branch.body))?;
+                        chain.extend(quote! { if #cond { #body } else });
+                    }
+                    let otherwise = match otherwise {
+                        Some(body) => Some(self.gen_body(body)?),
+                        None => None,
+                    };
+                    quote! { #chain { #otherwise } }
+                }
+                Part::For {
+                    pattern,
+                    iter,
+                    body,
+                    empty,
+                    ..
+                } => self.gen_for(pattern, iter, body, empty.as_ref())?,
+                Part::Match { expr, arms } => {
+                    let expr = borrow_place(self.gen_expr(expr)?);
+                    let arms = arms.iter().map(|arm| {
+                        let (pattern, guard) = parse_arm
+                            .parse_str(&arm.pattern)
+                            .map_err(|e| self.error("pattern", &arm.pattern, e))?;
+                        self.with_bindings(Some(&pattern), |gen| {
+                            let guard = match guard {
+                                Some(guard) => {
+                                    let guard = gen.parse_expr(guard, &arm.pattern)?;
+                                    Some(quote! { if #guard })
+                                }
+                                None => None,
+                            };
+                            let body = gen.gen_body(&arm.body)?;
+                            Ok(quote! { #pattern #guard => { #body } })
+                        })
+                    });
+                    let arms = arms.collect::<syn::Result<Vec<_>>>()?;
+                    quote! {
+                        match #expr {
+                            #( #arms )*
+                        }
+                    }
+                }
+            });
+        }
+        Ok(tokens)
+    }
+
+    ///Loops with a peekable iterator, so `loop.last` is known
+    ///before the body of the item is rendered.
+    fn gen_for(
+        &mut self,
+        pattern: &str,
+        iter: &str,
+        body: &Output,
+        empty: Option<&Output>,
+    ) -> syn::Result<TokenStream> {
+        let iter = borrow_place(self.gen_expr(iter)?);
+        let pattern = syn::Pat::parse_single
+            .parse_str(pattern)
+            .map_err(|e| self.error("pattern", pattern, e))?;
+        let empty = match empty {
+            Some(body) => {
+                let body = self.gen_body(body)?;
+                Some(quote! {
+                    if __slimr_iter.peek().is_none() {
+                        #body
+                    }
+                })
+            }
+            None => None,
+        };
+        self.locals.push("loop".to_string());
+        let body = self.with_bindings(Some(&pattern), |gen| gen.gen_body(body));
+        self.locals.pop();
+        let body = body?;
+        Ok(quote! {
+            {
+                let mut __slimr_iter = ::std::iter::IntoIterator::into_iter(#iter).peekable();
+                #empty
+                let mut __slimr_index = 0;
+                while let Some(__slimr_item) = __slimr_iter.next() {
+                    let #pattern = __slimr_item;
+                    let __slimr_loop = ::slimr::Loop {
+                        index: __slimr_index + 1,
+                        index0: __slimr_index,
+                        first: __slimr_index == 0,
+                        last: __slimr_iter.peek().is_none(),
+                    };
+                    #body
+                    __slimr_index += 1;
+                }
+            }
+        })
+    }
+
+    ///Generates the expression of the variable and passes it through its filters.
+    ///Built-in filters are in `slimr::filters`, other filters are functions in scope.
+    fn gen_var(&mut self, name: &str) -> syn::Result<TokenStream> {
+        let (source, filters) = match self.split_filters(name) {
+            Some((source, filters)) => {
+                let filters =
+                    expr::parse_filters(filters).map_err(|e| self.error("variable", name, e))?;
+                (source, filters)
+            }
+            None => (name, Vec::new()),
+        };
+        //The value is borrowed, so operators like `+` or `as` need parentheses
+        let mut value = match self.gen_expr(source)? {
+            expr @ (syn::Expr::Path(_)
+            | syn::Expr::Field(_)
+            | syn::Expr::MethodCall(_)
+            | syn::Expr::Call(_)
+            | syn::Expr::Index(_)
+            | syn::Expr::Lit(_)
+            | syn::Expr::Macro(_)
+            | syn::Expr::Paren(_)) => expr.into_token_stream(),
+            expr => quote! { (#expr) },
+        };
+        for filter in filters {
+            let ident = syn::Ident::new(&filter.name, Span::call_site());
+            let func = match filters::BUILTIN.contains(&filter.name.as_str()) {
+                true => quote! { ::slimr::filters::#ident },
+                false => ident.into_token_stream(),
+            };
+            let args = filter.args.iter().map(gen_literal);
+            value = quote! { #func(&#value #( , #args )*) };
+        }
+        Ok(value)
+    }
+
+    ///Splits a variable at the first `|` that follows a whole Rust
+    ///expression, so `||` and closures stay part of the expression.
+    fn split_filters<'s>(&self, var: &'s str) -> Option<(&'s str, &'s str)> {
+        var.match_indices('|').find_map(|(i, _)| {
+            let (source, filters) = (&var[..i], &var[i + 1..]);
+            if source.ends_with('|') || filters.starts_with('|') {
+                return None;
+            }
+            let tokens = syn::parse_str::<TokenStream>(source).ok()?;
+            syn::parse2::<syn::Expr>(self.rename_loop(tokens)).ok()?;
+            Some((source.trim(), filters))
+        })
+    }
+
+    ///Generates the condition of an `- if`. For `- if let`, the
+    ///pattern is returned as well, because it binds variables.
+    fn gen_cond(&mut self, cond: &str) -> syn::Result<(TokenStream, Option<syn::Pat>)> {
+        if !cond.starts_with("let ") {
+            return Ok((self.gen_expr(cond)?.into_token_stream(), None));
+        }
+        let (pattern, expr) = parse_let
+            .parse_str(cond)
+            .map_err(|e| self.error("condition", cond, e))?;
+        let expr = borrow_place(self.parse_expr(expr, cond)?);
+        Ok((quote! { let #pattern = #expr }, Some(pattern)))
+    }
+
+    ///Generates `f` with the variables that the pattern binds in scope.
+    fn with_bindings<T>(
+        &mut self,
+        pattern: Option<&syn::Pat>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let outer = self.locals.len();
+        if let Some(pattern) = pattern {
+            let mut bindings = Bindings(vec![]);
+            bindings.visit_pat(pattern);
+            self.locals.extend(bindings.0);
         }
+        let res = f(self);
+        self.locals.truncate(outer);
+        res
+    }
+
+    ///Parses a condition, the items of a loop or a variable as a Rust expression.
+    ///The fields of the struct can be used without `self.`, and the
+    ///fields of tuple structs by their index, like `0`.
+    fn gen_expr(&mut self, source: &str) -> syn::Result<syn::Expr> {
+        let tokens = syn::parse_str::<TokenStream>(source)
+            .map_err(|e| self.error("expression", source, e))?;
+        self.parse_expr(tokens, source)
+    }
+
+    fn parse_expr(&mut self, tokens: TokenStream, source: &str) -> syn::Result<syn::Expr> {
+        let mut expr = syn::parse2::<syn::Expr>(self.rename_loop(tokens))
+            .map_err(|e| self.error("expression", source, e))?;
+        let mut visitor = FieldAccess {
+            fields: &self.fields,
+            locals: self.locals.clone(),
+            used: &mut self.used,
+            unknown: Vec::new(),
+            macro_name: None,
+        };
+        visitor.tuple_field(&mut expr);
+        visitor.visit_expr_mut(&mut expr);
+        if let Some(name) = visitor.macro_name {
+            let message = format!(
+                "the arguments of `{}!` aren't expressions separated by commas, \
+                 use a field or a method instead",
+                name
+            );
+            return Err(self.error("expression", source, message));
+        }
+        match visitor
+            .unknown
+            .iter()
+            .find_map(|name| self.unknown_var(name))
+        {
+            Some(message) => Err(self.error("expression", source, message)),
+            None => Ok(expr),
+        }
+    }
+
+    ///`loop` is a keyword, so `loop.index` is replaced before it's parsed.
+    fn rename_loop(&self, tokens: TokenStream) -> TokenStream {
+        if !self.is_local("loop") {
+            return tokens;
+        }
+        tokens
+            .into_iter()
+            .map(|token| match token {
+                TokenTree::Ident(ident) if ident == "loop" => {
+                    TokenTree::Ident(syn::Ident::new("__slimr_loop", ident.span()))
+                }
+                TokenTree::Group(group) => {
+                    let mut renamed =
+                        Group::new(group.delimiter(), self.rename_loop(group.stream()));
+                    renamed.set_span(group.span());
+                    TokenTree::Group(renamed)
+                }
+                other => other,
+            })
+            .collect()
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|local| local == name)
+    }
+
+    ///Suggests the field or variable with the most similar name, if there is one.
+    fn unknown_var(&self, name: &str) -> Option<String> {
+        let (_, known) = self
+            .fields
+            .keys()
+            .chain(&self.locals)
+            .map(|known| (strsim::jaro_winkler(name, known), known))
+            .filter(|(similarity, _)| *similarity > 0.8)
+            .max_by(|a, b| a.0.total_cmp(&b.0))?;
+        Some(format!(
+            "there's no field or variable `{}`, did you mean `{}`?",
+            name, known
+        ))
+    }
+
+    ///An error in the Rust code of the template, like `Invalid pattern `Some(`: ...`.
+    fn error(&self, what: &str, source: &str, message: impl Display) -> syn::Error {
+        let message = format!("Invalid {} `{}`: {}", what, source, message);
+        syn::Error::new_spanned(&self.attr, message)
     }
-    res
 }
 
-fn classify_brace<I: Iterator<Item = char>>(
-    brace_type: String,
-    mut iter: &mut Peekable<I>,
-) -> String {
-    let mut res = String::new();
-    match iter.peek() {
-        Some(c) if c.to_string() == brace_type => {
-            iter.next();
-            res.push_str(&classify_double_brace(brace_type, &mut iter));
+///Fields are borrowed, so matching or looping doesn't move them.
+///Anything else, like `items.iter()`, is used as it is.
+fn borrow_place(expr: syn::Expr) -> TokenStream {
+    match expr {
+        syn::Expr::Field(_) | syn::Expr::Index(_) => quote! { &#expr },
+        syn::Expr::Paren(ref paren) if matches!(*paren.expr, syn::Expr::Unary(_)) => {
+            quote! { &#expr }
         }
-        _ => res.push_str(&brace_type.repeat(2)), //one brace becomes two
+        expr => expr.into_token_stream(),
     }
-    res
 }
 
-fn classify_double_brace<I: Iterator<Item = char>>(
-    brace_type: String,
-    mut iter: &mut Peekable<I>,
-) -> String {
-    let mut res = String::new();
-    match iter.peek() {
-        Some(c) if c.to_string() == brace_type => {
-            res.push_str(&brace_type.repeat(4)); //already saw two braces; this is the third
-            res.push_str(&continue_double_braces(brace_type, &mut iter))
+///Parses `let pattern = expr`. The expression is parsed later,
+///when `loop` has been renamed.
+fn parse_let(input: ParseStream) -> syn::Result<(syn::Pat, TokenStream)> {
+    input.parse::<syn::Token![let]>()?;
+    let pattern = syn::Pat::parse_multi_with_leading_vert(input)?;
+    input.parse::<syn::Token![=]>()?;
+    Ok((pattern, input.parse()?))
+}
+
+///Parses the pattern of a match arm and its guard, like `Some(n) if n > 3`.
+fn parse_arm(input: ParseStream) -> syn::Result<(syn::Pat, Option<TokenStream>)> {
+    let pattern = syn::Pat::parse_multi_with_leading_vert(input)?;
+    if input.parse::<Option<syn::Token![if]>>()?.is_none() {
+        return Ok((pattern, None));
+    }
+    Ok((pattern, Some(input.parse()?)))
+}
+
+///Replaces the names of fields with the expressions that read them, like
+///`self.name`, unless a local variable hides them. Remembers the fields
+///that are used and the first name that's neither a field nor a local variable.
+struct FieldAccess<'a> {
+    fields: &'a BTreeMap<String, syn::Expr>,
+    locals: Vec<String>,
+    used: &'a mut BTreeSet<String>,
+    ///Names that are neither fields nor locals. They may still be
+    ///functions or statics, so only typos of known names are reported.
+    unknown: Vec<String>,
+    ///A macro whose arguments can't be rewritten
+    macro_name: Option<String>,
+}
+
+impl FieldAccess<'_> {
+    ///Replaces an index like `0` with the field of a tuple struct. It's only
+    ///a field on its own or before `.`, like in `0.len()`, elsewhere it's a number.
+    fn tuple_field(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(int),
+            ..
+        }) = expr
+        {
+            let name = int.base10_digits();
+            if let (Some(access), "") = (self.fields.get(name), int.suffix()) {
+                self.used.insert(name.to_string());
+                *expr = access.clone();
+            }
         }
-        _ => {
-            res.push_str(&brace_type);
-            return res;
-        } // was a double brace, return single
     }
-    res
 }
 
-fn continue_double_braces<I: Iterator<Item = char>>(
-    brace_type: String,
-    iter: &mut Peekable<I>,
-) -> String {
-    let mut res = String::new();
-    while let Some(c) = iter.next() {
-        if c.to_string() == brace_type {
-            res.push_str(&brace_type.repeat(2));
-        } else {
-            break;
+impl VisitMut for FieldAccess<'_> {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Path(path) = expr {
+            if let Some(ident) = path.path.get_ident() {
+                let name = ident.to_string();
+                if path.qself.is_none() && !self.locals.contains(&name) {
+                    if let Some(access) = self.fields.get(&name) {
+                        *expr = access.clone();
+                        self.used.insert(name);
+                        return;
+                    }
+                    //Constants and unit structs start with an uppercase letter,
+                    //the generated variables with `__slimr`
+                    let is_var = name.starts_with(|c: char| c.is_lowercase() || c == '_');
+                    if is_var && name != "self" && !name.starts_with("__slimr") {
+                        self.unknown.push(name);
+                    }
+                }
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+
+    fn visit_expr_field_mut(&mut self, field: &mut syn::ExprField) {
+        self.tuple_field(&mut field.base);
+        visit_mut::visit_expr_field_mut(self, field);
+    }
+
+    fn visit_expr_method_call_mut(&mut self, call: &mut syn::ExprMethodCall) {
+        self.tuple_field(&mut call.receiver);
+        visit_mut::visit_expr_method_call_mut(self, call);
+    }
+
+    fn visit_expr_index_mut(&mut self, index: &mut syn::ExprIndex) {
+        self.tuple_field(&mut index.expr);
+        visit_mut::visit_expr_index_mut(self, index);
+    }
+
+    ///The function of a call isn't a variable, like `helper` in `helper(name)`.
+    fn visit_expr_call_mut(&mut self, call: &mut syn::ExprCall) {
+        if !matches!(&*call.func, syn::Expr::Path(_)) {
+            self.visit_expr_mut(&mut call.func);
+        }
+        for arg in call.args.iter_mut() {
+            self.visit_expr_mut(arg);
+        }
+    }
+
+    ///The arguments of macros like `format!` are rewritten as well, as long as
+    ///they are expressions. Names inside a format string, like `{name}`, aren't.
+    fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+        let parser = Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated;
+        match mac.parse_body_with(parser) {
+            Ok(mut args) => {
+                for arg in args.iter_mut() {
+                    self.tuple_field(arg);
+                    self.visit_expr_mut(arg);
+                }
+                mac.tokens = args.into_token_stream();
+            }
+            Err(_) => {
+                let name = mac.path.to_token_stream().to_string().replace(' ', "");
+                self.macro_name.get_or_insert(name);
+            }
+        }
+    }
+
+    fn visit_expr_closure_mut(&mut self, closure: &mut syn::ExprClosure) {
+        let outer = self.locals.len();
+        let mut bindings = Bindings(vec![]);
+        for input in &closure.inputs {
+            bindings.visit_pat(input);
         }
+        self.locals.extend(bindings.0);
+        self.visit_expr_mut(&mut closure.body);
+        self.locals.truncate(outer);
+    }
+}
+
+///Collects the names that a pattern binds.
+struct Bindings(Vec<String>);
+
+impl<'ast> Visit<'ast> for Bindings {
+    fn visit_pat_ident(&mut self, pat: &'ast syn::PatIdent) {
+        self.0.push(pat.ident.to_string());
+        visit::visit_pat_ident(self, pat);
+    }
+}
+
+fn gen_literal(value: &Value) -> TokenStream {
+    match value {
+        Value::Str(s) => s.into_token_stream(),
+        Value::Int(i) => Literal::i64_unsuffixed(*i).into_token_stream(),
+        Value::Float(x) => Literal::f64_unsuffixed(*x).into_token_stream(),
+        Value::Bool(b) => b.into_token_stream(),
+        other => unreachable!("filter arguments are literals, not {:?}", other),
+    }
+}
+
+///There are no custom warnings for derive macros, so an unused field
+///becomes the use of a deprecated item, which rustc warns about.
+fn gen_unused_warning(field: &syn::Field, name: &str) -> TokenStream {
+    let note = format!("the template doesn't use the field `{}`", name);
+    let span = field
+        .ident
+        .as_ref()
+        .map_or_else(|| field.ty.span(), |ident| ident.span());
+    let name = syn::Ident::new("UnusedField", span);
+    quote! {
+        const _: () = {
+            #[deprecated(note = #note)]
+            struct #name;
+            let _ = #name;
+        };
+    }
+}
+
+fn gen_escape(escape: Escape) -> TokenStream {
+    match escape {
+        Escape::Text => quote! { ::slimr::escape::Escape::Text },
+        Escape::Attr => quote! { ::slimr::escape::Escape::Attr },
+        Escape::Raw => quote! { ::slimr::escape::Escape::Raw },
     }
-    res
 }
 
 #[cfg(test)]
 mod tests {
-    use super::reformat_braces;
+    use super::*;
 
-    #[test]
-    fn formats_single_opening() {
-        assert_eq!(reformat_braces("{"), "{{");
+    fn error(input: syn::DeriveInput) -> String {
+        derive(&input).unwrap_err().to_string()
     }
 
     #[test]
-    fn formats_double_opening() {
-        assert_eq!(reformat_braces("{{"), "{");
+    fn reports_invalid_options() {
+        let message = error(syn::parse_quote! {
+            #[template(source = "p", path = "a.slimr")]
+            struct Page {}
+        });
+        assert!(message.starts_with("Please only provide one template source"));
+        let message = error(syn::parse_quote! {
+            struct Page {}
+        });
+        assert!(message.starts_with("Please provide either a path"));
+        let message = error(syn::parse_quote! {
+            #[template(source = "p", nope = true)]
+            struct Page {}
+        });
+        assert_eq!(message, "Unknown field: `nope`");
+        let message = error(syn::parse_quote! {
+            #[template(path = "missing.slimr")]
+            struct Page {}
+        });
+        assert!(message.starts_with("Couldn't load template missing.slimr"));
+        let message = error(syn::parse_quote! {
+            enum Page {
+                #[template(source = "p")]
+                Home,
+                About,
+            }
+        });
+        assert!(message.starts_with("Please provide either a path"));
     }
 
     #[test]
-    fn formats_multiple_opening() {
-        assert_eq!(reformat_braces("{{{"), "{{{{{{");
-        assert_eq!(reformat_braces("{{{{"), "{{{{{{{{");
+    fn reports_template_errors() {
+        let message = error(syn::parse_quote! {
+            #[template(source = "p\n  - when a\n    b")]
+            struct Page {}
+        });
+        assert_eq!(
+            message,
+            "`- when` needs to be inside a `- match` at line 2, column 3\n  - when a\n  ^"
+        );
+        let message = error(syn::parse_quote! {
+            #[template(source = "- for x in\n  p")]
+            struct Page {}
+        });
+        assert!(message.starts_with("expected"), "{}", message);
+        let message = error(syn::parse_quote! {
+            #[template(source = "- match a\n  - when Some(\n    p")]
+            struct Page {
+                a: Option<u8>,
+            }
+        });
+        assert!(
+            message.starts_with("Invalid pattern `Some(`"),
+            "{}",
+            message
+        );
     }
 
     #[test]
-    fn formats_single_closing() {
-        assert_eq!(reformat_braces("}"), "}}");
+    fn reports_invalid_variables() {
+        let message = error(syn::parse_quote! {
+            #[template(source = "p {{ name.len( }}")]
+            struct Page {
+                name: String,
+            }
+        });
+        assert!(
+            message.starts_with("Invalid expression `name.len(`"),
+            "{}",
+            message
+        );
+        let message = error(syn::parse_quote! {
+            #[template(source = "p {{ name | truncate( }}")]
+            struct Page {
+                name: String,
+            }
+        });
+        assert!(
+            message.starts_with("Invalid variable `name | truncate(`"),
+            "{}",
+            message
+        );
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[template(source = "p {{ n as u32 }} {{ -n }} {{ names.iter().map(|n| n.len()).sum::<usize>() | upper }}")]
+            struct Page {
+                n: i8,
+                names: Vec<String>,
+            }
+        };
+        assert!(derive(&input).is_ok());
     }
 
     #[test]
-    fn formats_double_closing() {
-        assert_eq!(reformat_braces("}}"), "}");
+    fn suggests_similar_fields() {
+        let message = error(syn::parse_quote! {
+            #[template(source = "p {{ nmae | upper }}")]
+            struct Page {
+                name: String,
+            }
+        });
+        assert_eq!(
+            message,
+            "Invalid expression `nmae`: there's no field or variable `nmae`, did you mean `name`?"
+        );
+        let message = error(syn::parse_quote! {
+            #[template(source = "- for item in items\n  p {{ itme.len() }}")]
+            struct Page {
+                items: Vec<String>,
+            }
+        });
+        assert!(message.ends_with("did you mean `item`?"), "{}", message);
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[template(source = "- if xyz\n  p {{ items.iter().map(shout).count() }}")]
+            struct Page {
+                items: Vec<String>,
+            }
+        };
+        assert!(derive(&input).is_ok());
     }
 
     #[test]
-    fn formats_multiple_closing() {
-        assert_eq!(reformat_braces("}}}"), "}}}}}}");
-        assert_eq!(reformat_braces("}}}}"), "}}}}}}}}");
+    fn rewrites_macro_arguments() {
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[template(source = "p {{ format!(\"{}!\", 0) }} {{ matches!(0.len(), 1 | 2) }}")]
+            struct Page(String);
+        };
+        let output = derive(&input).unwrap().to_string();
+        assert!(output.contains("(\"{}!\" , self . 0)"), "{}", output);
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[template(source = "p {{ format!(\"{}!\", name) }}")]
+            struct Page {
+                name: String,
+            }
+        };
+        let output = derive(&input).unwrap().to_string();
+        assert!(output.contains("self . name"), "{}", output);
+        let message = error(syn::parse_quote! {
+            #[template(source = "p {{ println!(name; 1) }}")]
+            struct Page {
+                name: String,
+            }
+        });
+        assert!(
+            message.contains("the arguments of `println!`"),
+            "{}",
+            message
+        );
     }
 
     #[test]
-    fn formats_var() {
-        assert_eq!(reformat_braces("{{hello}}"), "{hello}");
+    fn allows_functions_and_constants() {
+        let input: syn::DeriveInput = syn::parse_quote! {
+            #[template(source = "- if helper(name) && name.len() < MAX\n  p {{ name }}\n- if tags.iter().any(|tag| tag == name)\n  p")]
+            struct Page {
+                name: String,
+                tags: Vec<String>,
+            }
+        };
+        assert!(derive(&input).is_ok());
     }
-
-    #[test]
-    fn formats_open_close_pair() {
-        assert_eq!(reformat_braces("{}"), "{{}}");
-    }
-
-    // #[test]
-    // fn formats_escaped_double_braces() {
-    //     //remove the '\' and double braces to escape it format!
-    //     assert_eq!(reformat_braces(r#"\{{"#), "{{{{");
-    //     //assert_eq!(reformat_braces("\\}}"), "}}}}")
-    // }
-
-    // #[test]
-    // fn formats_backslash_triple_braces() {
-    //     //the backslash should only be removed on double braces
-    //     assert_eq!(reformat_braces("\\{{{"), "\\{{{{{{");
-    //     assert_eq!(reformat_braces("\\}}}"), "\\}}}}}}")
-    // }
 }
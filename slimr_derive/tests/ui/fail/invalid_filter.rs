@@ -0,0 +1,10 @@
+use slimr::SlimR;
+
+#[derive(SlimR)]
+#[template(source = "p {{ name | truncate(len) }}")]
+struct Page {
+    name: String,
+    len: usize,
+}
+
+fn main() {}
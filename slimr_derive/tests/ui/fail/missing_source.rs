@@ -0,0 +1,14 @@
+use slimr::SlimR;
+
+#[derive(SlimR)]
+#[template(pretty)]
+struct Page {}
+
+#[derive(SlimR)]
+enum Tabs {
+    #[template(source = "p Home")]
+    Home,
+    About,
+}
+
+fn main() {}
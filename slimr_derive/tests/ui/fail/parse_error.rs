@@ -0,0 +1,9 @@
+use slimr::SlimR;
+
+#[derive(SlimR)]
+#[template(source = "ul\n  - when a\n    li")]
+struct Page {
+    a: u8,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use slimr::SlimR;
+
+#[derive(SlimR)]
+#[template(source = "h1 {{ titel }}")]
+struct Page {
+    title: String,
+}
+
+fn main() {}
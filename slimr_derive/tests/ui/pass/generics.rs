@@ -0,0 +1,62 @@
+use std::fmt::Display;
+
+use slimr::SlimR;
+
+#[derive(SlimR)]
+#[template(source = "p {{title}}\n- for cell in cells\n  b {{cell}}")]
+struct Grid<'a, const N: usize> {
+    title: &'a str,
+    cells: [u8; N],
+}
+
+#[derive(SlimR)]
+#[template(source = "p {{first}} {{second}}")]
+struct Pair<'a, 'b: 'a> {
+    first: &'a str,
+    second: &'b str,
+}
+
+#[derive(SlimR)]
+#[template(source = "- for item in items\n  li {{item}}")]
+struct List<T>
+where
+    T: Display,
+{
+    items: Vec<T>,
+}
+
+#[derive(SlimR)]
+#[template(source = "p {{value}}")]
+struct Labeled<T: Display + ?Sized = str> {
+    value: Box<T>,
+}
+
+#[derive(SlimR)]
+enum Either<L: Display, R>
+where
+    R: Display,
+{
+    #[template(source = "p {{0}}")]
+    Left(L),
+    #[template(source = "b {{0}}")]
+    Right(R),
+}
+
+fn main() {
+    let grid = Grid {
+        title: "Grid",
+        cells: [1, 2],
+    };
+    assert_eq!(grid.render(), "<p>Grid</p><b>1</b><b>2</b>");
+    let second = String::from("b");
+    let pair = Pair {
+        first: "a",
+        second: &second,
+    };
+    assert_eq!(pair.render(), "<p>a b</p>");
+    let list = List { items: vec![1.5] };
+    assert_eq!(list.render(), "<li>1.5</li>");
+    let labeled: Labeled = Labeled { value: "Neo".into() };
+    assert_eq!(labeled.render(), "<p>Neo</p>");
+    assert_eq!(Either::<u8, &str>::Right("r").render(), "<b>r</b>");
+}
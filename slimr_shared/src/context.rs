@@ -0,0 +1,433 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use crate::renderer::RenderError;
+
+///A value that templates can use at runtime.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    ///Renders as nothing
+    #[default]
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    ///Renders its items separated by `, `
+    List(Vec<Value>),
+    ///An object with named fields
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    ///Returns the field of a map or the item of a list at an index like `0`.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(name),
+            Value::List(items) => items.get(name.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    ///Whether the value counts as true in a condition. Null, false,
+    ///zero and empty strings, lists and maps are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Int(i) => *i != 0,
+            Value::Float(x) => *x != 0.0,
+            Value::Str(s) => !s.is_empty(),
+            Value::List(items) => !items.is_empty(),
+            Value::Map(map) => !map.is_empty(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json)
+            .expect("writing to a String can't fail");
+        json
+    }
+
+    fn write_json(&self, out: &mut String) -> fmt::Result {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Float(x) if !x.is_finite() => out.push_str("null"),
+            Value::Bool(_) | Value::Int(_) | Value::Float(_) => write!(out, "{}", self)?,
+            Value::Str(s) => write_json_str(out, s)?,
+            Value::List(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    item.write_json(out)?;
+                }
+                out.push(']');
+            }
+            Value::Map(map) => {
+                out.push('{');
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_json_str(out, key)?;
+                    out.push(':');
+                    value.write_json(out)?;
+                }
+                out.push('}');
+            }
+        }
+        Ok(())
+    }
+
+    ///Converts anything that implements `serde::Serialize`.
+    #[cfg(feature = "serde")]
+    pub fn from_serialize<T: serde::Serialize + ?Sized>(value: &T) -> Result<Value, RenderError> {
+        serde_json::to_value(value)
+            .map(Value::from)
+            .map_err(|e| RenderError::Serialize(e.to_string()))
+    }
+}
+
+fn write_json_str(out: &mut String, s: &str) -> fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                Ok(())
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+macro_rules! value_from {
+    ($variant:ident: $as:ty, $($t:ty),+) => {
+        $(impl From<$t> for Value {
+            fn from(value: $t) -> Value {
+                Value::$variant(value as $as)
+            }
+        })+
+    };
+}
+
+value_from!(Int: i64, i8, i16, i32, i64, u8, u16, u32, isize);
+
+//Like numbers from serde, integers that don't fit into an `i64` become floats
+macro_rules! value_from_wide {
+    ($($t:ty),+) => {
+        $(impl From<$t> for Value {
+            fn from(value: $t) -> Value {
+                i64::try_from(value).map_or(Value::Float(value as f64), Value::Int)
+            }
+        })+
+    };
+}
+
+value_from_wide!(u64, usize, i128, u128);
+value_from!(Float: f64, f32, f64);
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Str(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Value {
+        Value::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> From<BTreeMap<K, V>> for Value {
+    fn from(map: BTreeMap<K, V>) -> Value {
+        Value::Map(map.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> From<HashMap<K, V>> for Value {
+    fn from(map: HashMap<K, V>) -> Value {
+        Value::Map(map.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+///The variables of a template that is rendered at runtime.
+///```
+///# use slimr_shared::context::Context;
+///let context = Context::new()
+///    .with("name", "Neo")
+///    .with("age", 37)
+///    .with("skills", vec!["kung fu", "flying"]);
+///```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Context {
+    values: BTreeMap<String, Value>,
+    filters: Filters,
+}
+
+///A filter of runtime templates. It gets the value and the arguments
+///of the filter, like `20` in `{{ name | truncate(20) }}`.
+pub type FilterFn = dyn Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync;
+
+#[derive(Clone, Default)]
+struct Filters(BTreeMap<String, Arc<FilterFn>>);
+
+impl fmt::Debug for Filters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+///Filters are compared by their names.
+impl PartialEq for Filters {
+    fn eq(&self, other: &Filters) -> bool {
+        self.0.keys().eq(other.0.keys())
+    }
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    pub fn with(mut self, name: impl Into<String>, value: impl Into<Value>) -> Context {
+        self.insert(name, value);
+        self
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<Value>) {
+        self.values.insert(name.into(), value.into());
+    }
+
+    ///Adds a filter that templates can use like the built-in ones.
+    ///```
+    ///# use slimr_shared::context::{Context, Value};
+    ///let context = Context::new().with_filter("shout", |value, _| Ok(format!("{}!", value).into()));
+    ///```
+    pub fn with_filter<F>(mut self, name: impl Into<String>, filter: F) -> Context
+    where
+        F: Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.filters.0.insert(name.into(), Arc::new(filter));
+        self
+    }
+
+    pub fn filter(&self, name: &str) -> Option<&FilterFn> {
+        self.filters.0.get(name).map(|filter| filter.as_ref())
+    }
+
+    ///Looks up a variable. Fields and list items are reached
+    ///with dotted paths like `user.address.city` or `posts.0.title`.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let mut names = path.split('.');
+        let value = self.values.get(names.next()?)?;
+        names.try_fold(value, |value, name| value.get(name))
+    }
+
+    ///Uses the fields of a struct or the entries of a map as variables.
+    #[cfg(feature = "serde")]
+    pub fn from_serialize<T: serde::Serialize + ?Sized>(value: &T) -> Result<Context, RenderError> {
+        match Value::from_serialize(value)? {
+            Value::Map(values) => Ok(Context {
+                values,
+                ..Context::default()
+            }),
+            other => Err(RenderError::Serialize(format!(
+                "expected a struct or a map, found {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Value {
+        use serde_json::Value as Json;
+        match value {
+            Json::Null => Value::Null,
+            Json::Bool(b) => Value::Bool(b),
+            Json::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(s) => Value::Str(s),
+            Json::Array(items) => items.into(),
+            Json::Object(map) => Value::Map(map.into_iter().map(|(k, v)| (k, v.into())).collect()),
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> From<BTreeMap<K, V>> for Context {
+    fn from(map: BTreeMap<K, V>) -> Context {
+        Context {
+            values: map.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+            ..Context::default()
+        }
+    }
+}
+
+impl<K, V> From<&BTreeMap<K, V>> for Context
+where
+    K: Clone + Into<String>,
+    V: Clone + Into<Value>,
+{
+    fn from(map: &BTreeMap<K, V>) -> Context {
+        map.clone().into()
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> From<HashMap<K, V>> for Context {
+    fn from(map: HashMap<K, V>) -> Context {
+        Context {
+            values: map.into_iter().map(|(k, v)| (k.into(), v.into())).collect(),
+            ..Context::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_values() {
+        assert_eq!(Value::from(3u8), Value::Int(3));
+        assert_eq!(Value::from(3usize), Value::Int(3));
+        assert_eq!(Value::from(u64::MAX), Value::Float(u64::MAX as f64));
+        assert_eq!(Value::from(1.5f32), Value::Float(1.5));
+        assert_eq!(Value::from(None::<&str>), Value::Null);
+        assert_eq!(
+            Value::from(vec![1, 2]),
+            Value::List(vec![Value::Int(1), Value::Int(2)])
+        );
+    }
+
+    #[test]
+    fn displays_values() {
+        let mut user = BTreeMap::new();
+        user.insert("name", Value::from("Neo"));
+        user.insert("tags", Value::from(vec!["one", "chosen"]));
+        assert_eq!(
+            Value::from(user).to_string(),
+            "{name: Neo, tags: one, chosen}"
+        );
+        assert_eq!(Value::Null.to_string(), "");
+        assert_eq!(Value::from(false).to_string(), "false");
+        assert_eq!(Value::from(2.0).to_string(), "2");
+    }
+
+    #[test]
+    fn checks_truthiness() {
+        assert!(!Value::Null.is_truthy());
+        assert!(!Value::from(0).is_truthy());
+        assert!(!Value::from("").is_truthy());
+        assert!(!Value::from(Vec::<i32>::new()).is_truthy());
+        assert!(Value::from(0.5).is_truthy());
+        assert!(Value::from(vec![0]).is_truthy());
+    }
+
+    #[test]
+    fn builds_contexts() {
+        let mut map = BTreeMap::new();
+        map.insert("name", "Neo");
+        let context = Context::from(&map).with("age", 37);
+        assert_eq!(context.get("name"), Some(&Value::from("Neo")));
+        assert_eq!(context.get("age"), Some(&Value::Int(37)));
+        assert_eq!(context.get("missing"), None);
+    }
+
+    #[test]
+    fn finds_dotted_paths() {
+        let mut address = BTreeMap::new();
+        address.insert("city", Value::from("Zion"));
+        let mut user = BTreeMap::new();
+        user.insert("address", Value::from(address));
+        user.insert("aliases", Value::from(vec!["Neo", "The One"]));
+        let context = Context::new().with("user", user);
+        assert_eq!(context.get("user.address.city"), Some(&Value::from("Zion")));
+        assert_eq!(context.get("user.aliases.1"), Some(&Value::from("The One")));
+        assert_eq!(context.get("user.address.street"), None);
+        assert_eq!(context.get("user.aliases.2"), None);
+        assert_eq!(context.get("user.address.city.name"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn converts_serializable_values() {
+        let json = serde_json::json!({
+            "name": "Neo",
+            "age": 37,
+            "height": 1.85,
+            "address": { "city": "Zion" },
+            "tags": ["one", null]
+        });
+        let context = Context::from_serialize(&json).unwrap();
+        assert_eq!(context.get("age"), Some(&Value::Int(37)));
+        assert_eq!(context.get("height"), Some(&Value::Float(1.85)));
+        assert_eq!(context.get("address.city"), Some(&Value::from("Zion")));
+        assert_eq!(context.get("tags.1"), Some(&Value::Null));
+        assert_eq!(
+            Context::from_serialize(&[1, 2]).unwrap_err(),
+            RenderError::Serialize("expected a struct or a map, found 1, 2".to_string())
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{self, Write};
+
+///Where a value is inserted into the html. It decides
+///which characters have to be escaped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Escape {
+    ///Text content, escapes `&`, `<` and `>`
+    Text,
+    ///A quoted attribute value, also escapes both kinds of quotes
+    Attr,
+    ///Trusted html that is inserted as it is
+    Raw,
+}
+
+///Writes the escaped text.
+pub fn escape_str<W: fmt::Write + ?Sized>(out: &mut W, escape: Escape, text: &str) -> fmt::Result {
+    write_entities(out, escape, text, false)
+}
+
+///Writes the escaped text of a template. Unlike values, templates can
+///contain entities like `&nbsp;`, which are kept as they are.
+///Attributes are always written in double quotes, so single quotes are kept too.
+pub fn escape_static<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    escape: Escape,
+    text: &str,
+) -> fmt::Result {
+    write_entities(out, escape, text, true)
+}
+
+fn write_entities<W: fmt::Write + ?Sized>(
+    out: &mut W,
+    escape: Escape,
+    text: &str,
+    is_static: bool,
+) -> fmt::Result {
+    let mut last = 0;
+    for (i, c) in text.char_indices() {
+        let entity = match (c, escape) {
+            (_, Escape::Raw) => continue,
+            ('&', _) if is_static && starts_with_entity(&text[i..]) => continue,
+            ('&', _) => "&amp;",
+            ('<', _) => "&lt;",
+            ('>', _) => "&gt;",
+            ('"', Escape::Attr) => "&quot;",
+            ('\'', Escape::Attr) if !is_static => "&#39;",
+            _ => continue,
+        };
+        out.write_str(&text[last..i])?;
+        out.write_str(entity)?;
+        last = i + c.len_utf8();
+    }
+    out.write_str(&text[last..])
+}
+
+///Checks for a named or numeric character reference like `&amp;`, `&#39;` or `&#x27;`.
+fn starts_with_entity(text: &str) -> bool {
+    let end = match text.find(';') {
+        Some(end) => end,
+        None => return false,
+    };
+    let name = &text[1..end];
+    if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit())
+    } else if let Some(dec) = name.strip_prefix('#') {
+        !dec.is_empty() && dec.chars().all(|c| c.is_ascii_digit())
+    } else {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+}
+
+///Returns the escaped text.
+pub fn escape(escape: Escape, text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    escape_str(&mut res, escape, text).expect("writing to a String can't fail");
+    res
+}
+
+///Writes the escaped `Display` output of a value.
+///This is what the derived templates use for their variables.
+pub fn write_escaped<W, T>(out: &mut W, escape: Escape, value: &T) -> fmt::Result
+where
+    W: fmt::Write + ?Sized,
+    T: fmt::Display + ?Sized,
+{
+    match escape {
+        Escape::Raw => write!(out, "{}", value),
+        _ => write!(Escaper { out, escape }, "{}", value),
+    }
+}
+
+///Escapes everything that's written through it.
+struct Escaper<'a, W: ?Sized> {
+    out: &'a mut W,
+    escape: Escape,
+}
+
+impl<W: fmt::Write + ?Sized> Write for Escaper<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape_str(self.out, self.escape, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text() {
+        assert_eq!(
+            escape(Escape::Text, "<b>Tom & \"Jerry\"</b>"),
+            "&lt;b&gt;Tom &amp; \"Jerry\"&lt;/b&gt;"
+        );
+        assert_eq!(escape(Escape::Text, "plain ünïcode"), "plain ünïcode");
+    }
+
+    #[test]
+    fn escapes_attributes() {
+        assert_eq!(
+            escape(Escape::Attr, "\" onclick='alert(1)'"),
+            "&quot; onclick=&#39;alert(1)&#39;"
+        );
+    }
+
+    #[test]
+    fn keeps_entities_in_templates() {
+        let mut out = String::new();
+        escape_static(
+            &mut out,
+            Escape::Text,
+            "Tom&nbsp;&amp; Jerry &#169; &#xA9; & <3",
+        )
+        .unwrap();
+        assert_eq!(out, "Tom&nbsp;&amp; Jerry &#169; &#xA9; &amp; &lt;3");
+        assert_eq!(
+            escape(Escape::Text, "&nbsp; &#; &x y;"),
+            "&amp;nbsp; &amp;#; &amp;x y;"
+        );
+        let mut out = String::new();
+        escape_static(&mut out, Escape::Text, "&#; &x y; &#xZ;").unwrap();
+        assert_eq!(out, "&amp;#; &amp;x y; &amp;#xZ;");
+    }
+
+    #[test]
+    fn keeps_raw_html() {
+        assert_eq!(escape(Escape::Raw, "<b>bold</b>"), "<b>bold</b>");
+    }
+
+    #[test]
+    fn escapes_display_values() {
+        let mut out = String::new();
+        write_escaped(&mut out, Escape::Text, &format_args!("{}<{}", 1, 2)).unwrap();
+        write_escaped(&mut out, Escape::Raw, "<br>").unwrap();
+        assert_eq!(out, "1&lt;2<br>");
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Write};
+
+///The filters that every template can use, like `{{ name | upper }}`.
+///Derived templates call the functions of this module for them.
+pub const BUILTIN: [&str; 9] = [
+    "upper",
+    "lower",
+    "trim",
+    "truncate",
+    "default",
+    "join",
+    "length",
+    "urlencode",
+    "json",
+];
+
+pub fn upper(value: &(impl Display + ?Sized)) -> String {
+    value.to_string().to_uppercase()
+}
+
+pub fn lower(value: &(impl Display + ?Sized)) -> String {
+    value.to_string().to_lowercase()
+}
+
+pub fn trim(value: &(impl Display + ?Sized)) -> String {
+    value.to_string().trim().to_string()
+}
+
+///Keeps the first `len` characters and adds `...` if anything was cut off.
+pub fn truncate(value: &(impl Display + ?Sized), len: usize) -> String {
+    let text = value.to_string();
+    match text.char_indices().nth(len) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    }
+}
+
+///Replaces a missing or empty value.
+pub fn default(value: &(impl Optional + ?Sized), default: impl Display) -> String {
+    value.value().unwrap_or_else(|| default.to_string())
+}
+
+pub fn join<I>(values: I, separator: &str) -> String
+where
+    I: IntoIterator,
+    I::Item: Display,
+{
+    let mut res = String::new();
+    for (i, value) in values.into_iter().enumerate() {
+        if i > 0 {
+            res.push_str(separator);
+        }
+        write!(res, "{}", value).expect("writing to a String can't fail");
+    }
+    res
+}
+
+///The number of characters of a string or the number of items of a collection.
+pub fn length(value: &(impl Length + ?Sized)) -> usize {
+    value.length()
+}
+
+///Percent-encodes everything except letters, digits and `-_.~`.
+pub fn urlencode(value: &(impl Display + ?Sized)) -> String {
+    let text = value.to_string();
+    let mut res = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                res.push(byte as char)
+            }
+            _ => write!(res, "%{:02X}", byte).expect("writing to a String can't fail"),
+        }
+    }
+    res
+}
+
+///Writes the value as JSON. A value that can't be serialized
+///makes the rendering fail.
+#[cfg(feature = "serde")]
+pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Json<'_, T> {
+    Json(value)
+}
+
+///Derived templates can only use `json` with the `serde` feature.
+#[cfg(not(feature = "serde"))]
+pub fn json<T: NeedsSerde + ?Sized>(_value: &T) -> String {
+    unreachable!("the `json` filter needs the `serde` feature")
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "serde"))]
+#[diagnostic::on_unimplemented(
+    message = "the `json` filter needs the `serde` feature of slimr",
+    label = "can't be serialized without the `serde` feature"
+)]
+pub trait NeedsSerde {}
+
+///The result of the `json` filter.
+#[cfg(feature = "serde")]
+pub struct Json<'a, T: ?Sized>(&'a T);
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + ?Sized> Display for Json<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let json = serde_json::to_string(self.0).map_err(|_| std::fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+///Values that can be missing, for the `default` filter.
+pub trait Optional {
+    fn value(&self) -> Option<String>;
+}
+
+impl<T: Display> Optional for Option<T> {
+    fn value(&self) -> Option<String> {
+        self.as_ref().map(ToString::to_string)
+    }
+}
+
+impl Optional for str {
+    fn value(&self) -> Option<String> {
+        (!self.is_empty()).then(|| self.to_string())
+    }
+}
+
+impl Optional for String {
+    fn value(&self) -> Option<String> {
+        self.as_str().value()
+    }
+}
+
+impl<T: Optional + ?Sized> Optional for &T {
+    fn value(&self) -> Option<String> {
+        (**self).value()
+    }
+}
+
+///Strings and collections, for the `length` filter.
+pub trait Length {
+    fn length(&self) -> usize;
+}
+
+macro_rules! length_of_collection {
+    ($($t:ty),+) => {
+        $(impl<T> Length for $t {
+            fn length(&self) -> usize {
+                self.len()
+            }
+        })+
+    };
+}
+
+length_of_collection!([T], Vec<T>, BTreeSet<T>);
+
+impl<K, V> Length for BTreeMap<K, V> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V, S> Length for HashMap<K, V, S> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Length for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl Length for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T: Length + ?Sized> Length for &T {
+    fn length(&self) -> usize {
+        (**self).length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_filters() {
+        assert_eq!(upper("Neo"), "NEO");
+        assert_eq!(trim(" Neo\n"), "Neo");
+        assert_eq!(truncate("Trinity", 3), "Tri...");
+        assert_eq!(truncate("Neo", 3), "Neo");
+        assert_eq!(truncate("äöü", 2), "äö...");
+        assert_eq!(default(&None::<u8>, "none"), "none");
+        assert_eq!(default("", 0), "0");
+        assert_eq!(default(&Some(1), 0), "1");
+        assert_eq!(join([1, 2, 3], "-"), "1-2-3");
+        assert_eq!(length("äö"), 2);
+        assert_eq!(length(&vec![1, 2]), 2);
+        assert_eq!(urlencode("a b&c=ä"), "a%20b%26c%3D%C3%A4");
+    }
+}
@@ -0,0 +1,619 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::{fmt, io};
+
+use crate::context::{Context, Value};
+use crate::escape;
+use crate::filters;
+use crate::output::{Output, Part};
+use crate::parser::expr::{BinOp, Expr, Filter, Var};
+use crate::renderer::{IoError, RenderError, Result};
+use crate::write::IoWriter;
+
+///Fills in the variables of a rendered template from the context
+///and writes the html into `w`.
+pub fn render_into(output: &Output, context: &Context, w: &mut impl fmt::Write) -> Result<()> {
+    check_filters(output, context)?;
+    let mut scope = Scope {
+        context,
+        locals: Vec::new(),
+    };
+    scope.render_into(output, w)
+}
+
+///Like `render_into`, but writes into a file, a socket or any other
+///`io::Write`. The html is written while the variables are filled in,
+///so a failed rendering can leave part of it written.
+pub fn render_into_io(output: &Output, context: &Context, w: &mut impl io::Write) -> Result<()> {
+    let mut writer = IoWriter::new(w);
+    match render_into(output, context, &mut writer) {
+        Err(RenderError::Write(e)) => writer
+            .finish(Err(e))
+            .map_err(|e| RenderError::Io(IoError(Arc::new(e)))),
+        result => result,
+    }
+}
+
+pub fn render(output: &Output, context: &Context) -> Result<String> {
+    let mut html = String::with_capacity(output.static_len());
+    render_into(output, context, &mut html)?;
+    Ok(html)
+}
+
+///Checks that every filter exists before anything is written, including
+///the filters in branches and loops that aren't rendered. Custom filters
+///are only known once the context is.
+fn check_filters(output: &Output, context: &Context) -> Result<()> {
+    for part in output.parts() {
+        if let Part::Var {
+            name,
+            parsed: Some(var),
+            ..
+        } = part
+        {
+            let unknown = var.filters.iter().find(|filter| {
+                !filters::BUILTIN.contains(&filter.name.as_str())
+                    && context.filter(&filter.name).is_none()
+            });
+            if let Some(filter) = unknown {
+                return Err(expr_error(name)(format!(
+                    "unknown filter `{}`",
+                    filter.name
+                )));
+            }
+        }
+        for body in part.bodies() {
+            check_filters(body, context)?;
+        }
+    }
+    Ok(())
+}
+
+///The context and the variables of the loops around the current part.
+struct Scope<'a> {
+    context: &'a Context,
+    ///The variables of the innermost loop come last
+    locals: Vec<(String, Value)>,
+}
+
+impl Scope<'_> {
+    fn render_into(&mut self, output: &Output, w: &mut impl fmt::Write) -> Result<()> {
+        for part in output.parts() {
+            match part {
+                Part::Static(html) => w.write_str(html)?,
+                Part::Var {
+                    name,
+                    escape,
+                    parsed,
+                } => {
+                    let var = self.var(name, parsed_expr(parsed, name)?)?;
+                    escape::write_escaped(w, *escape, &var)?;
+                }
+                Part::If {
+                    branches,
+                    otherwise,
+                } => {
+                    let mut body = otherwise.as_ref();
+                    for branch in branches {
+                        let cond = parsed_expr(&branch.parsed, &branch.cond)?;
+                        if self.is_true(cond, &branch.cond)? {
+                            body = Some(&branch.body);
+                            break;
+                        }
+                    }
+                    if let Some(body) = body {
+                        self.render_into(body, w)?;
+                    }
+                }
+                Part::For {
+                    pattern,
+                    iter,
+                    parsed,
+                    body,
+                    empty,
+                } => {
+                    let source = || format!("{} in {}", pattern, iter);
+                    let expr = parsed_expr(parsed, &source())?;
+                    let items = self.items(expr, &source)?;
+                    if items.is_empty() {
+                        if let Some(empty) = empty {
+                            self.render_into(empty, w)?;
+                        }
+                    }
+                    let len = items.len();
+                    for (i, item) in items.into_iter().enumerate() {
+                        let meta = [
+                            ("index", Value::Int(i as i64 + 1)),
+                            ("index0", Value::Int(i as i64)),
+                            ("first", Value::from(i == 0)),
+                            ("last", Value::from(i + 1 == len)),
+                        ];
+                        self.locals
+                            .push(("loop".to_string(), BTreeMap::from(meta).into()));
+                        self.locals.push((pattern.clone(), item));
+                        let result = self.render_into(body, w);
+                        self.locals.truncate(self.locals.len() - 2);
+                        result?;
+                    }
+                }
+                Part::Match { expr, .. } => {
+                    return Err(RenderError::Expr {
+                        expr: expr.clone(),
+                        message: "`- match` is only supported in derived templates".to_string(),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    ///Looks up a variable and applies its filters. A missing variable
+    ///is an error, unless its first filter is `default`.
+    fn var(&self, name: &str, var: &Var) -> Result<Cow<'_, Value>> {
+        let value = match &var.expr {
+            Expr::Path(path) => match self.get(path) {
+                Some(value) => Cow::Borrowed(value),
+                None if var.filters.first().is_some_and(|f| f.name == "default") => {
+                    Cow::Owned(Value::Null)
+                }
+                None => return Err(RenderError::MissingVar(path.to_string())),
+            },
+            other => Cow::Owned(self.eval(other).map_err(expr_error(name))?.into_owned()),
+        };
+        var.filters.iter().try_fold(value, |value, filter| {
+            self.apply(filter, value)
+                .map(Cow::Owned)
+                .map_err(expr_error(name))
+        })
+    }
+
+    fn apply(&self, filter: &Filter, value: Cow<Value>) -> Result<Value, String> {
+        let arg = |i: usize| filter.args.get(i);
+        Ok(match filter.name.as_str() {
+            "upper" => Value::Str(filters::upper(&*value)),
+            "lower" => Value::Str(filters::lower(&*value)),
+            "trim" => Value::Str(filters::trim(&*value)),
+            "truncate" => match arg(0) {
+                Some(Value::Int(len)) if *len >= 0 => {
+                    Value::Str(filters::truncate(&*value, *len as usize))
+                }
+                _ => return Err("`truncate` needs a length, like `truncate(20)`".to_string()),
+            },
+            "default" => match &*value {
+                Value::Null => arg(0).cloned().unwrap_or_default(),
+                Value::Str(s) if s.is_empty() => arg(0).cloned().unwrap_or_default(),
+                _ => value.into_owned(),
+            },
+            "join" => {
+                let separator = match arg(0) {
+                    Some(Value::Str(separator)) => separator.as_str(),
+                    _ => ", ",
+                };
+                match &*value {
+                    Value::List(items) => Value::Str(filters::join(items, separator)),
+                    _ => value.into_owned(),
+                }
+            }
+            "length" => {
+                let len = match &*value {
+                    Value::Null => 0,
+                    Value::Str(s) => filters::length(s),
+                    Value::List(items) => items.len(),
+                    Value::Map(map) => map.len(),
+                    other => filters::length(&other.to_string()),
+                };
+                Value::Int(len as i64)
+            }
+            "urlencode" => Value::Str(filters::urlencode(&*value)),
+            "json" => Value::Str(value.to_json()),
+            name => match self.context.filter(name) {
+                Some(filter_fn) => filter_fn(&value, &filter.args)?,
+                None => return Err(format!("unknown filter `{}`", name)),
+            },
+        })
+    }
+
+    ///Looks up a dotted path in the loop variables and then in the context.
+    fn get(&self, path: &str) -> Option<&Value> {
+        let mut names = path.split('.');
+        let first = names.next()?;
+        match self.locals.iter().rev().find(|(name, _)| name == first) {
+            Some((_, value)) => names.try_fold(value, |value, name| value.get(name)),
+            None => self.context.get(path),
+        }
+    }
+
+    ///Evaluates a condition. Paths that aren't in the context are null, so
+    ///`- if user.nickname` is false for users without a nickname.
+    fn is_true(&self, cond: &Expr, source: &str) -> Result<bool> {
+        let value = self.eval(cond).map_err(expr_error(source))?;
+        Ok(value.is_truthy())
+    }
+
+    ///The items of a list to loop over. A null list has no items,
+    ///but a path that isn't in the context is an error like in `{{ }}`.
+    fn items(&self, iter: &Expr, source: &impl Fn() -> String) -> Result<Vec<Value>> {
+        let error = |message| RenderError::Expr {
+            expr: source(),
+            message,
+        };
+        if let Expr::Path(path) = iter {
+            if self.get(path).is_none() {
+                return Err(RenderError::MissingVar(path.to_string()));
+            }
+        }
+        match self.eval(iter).map_err(error)?.into_owned() {
+            Value::List(items) => Ok(items),
+            Value::Null => Ok(Vec::new()),
+            other => Err(error(format!("can't loop over {:?}", other))),
+        }
+    }
+
+    fn eval<'a>(&'a self, expr: &'a Expr) -> Result<Cow<'a, Value>, String> {
+        Ok(match expr {
+            Expr::Lit(value) => Cow::Borrowed(value),
+            Expr::Path(path) => self
+                .get(path)
+                .map_or(Cow::Owned(Value::Null), Cow::Borrowed),
+            Expr::Index(value, index) => {
+                let key = match &*self.eval(index)? {
+                    Value::Str(key) => key.clone(),
+                    Value::Int(i) => i.to_string(),
+                    other => return Err(format!("can't index with {:?}", other)),
+                };
+                match self.eval(value)? {
+                    Cow::Borrowed(value) => value
+                        .get(&key)
+                        .map_or(Cow::Owned(Value::Null), Cow::Borrowed),
+                    Cow::Owned(value) => Cow::Owned(value.get(&key).cloned().unwrap_or_default()),
+                }
+            }
+            Expr::Call(value, method, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Cow::Owned(call(&*self.eval(value)?, method, &args)?)
+            }
+            Expr::Not(expr) => Cow::Owned(Value::Bool(!self.eval(expr)?.is_truthy())),
+            Expr::Binary(left, BinOp::And, right) => Cow::Owned(Value::Bool(
+                self.eval(left)?.is_truthy() && self.eval(right)?.is_truthy(),
+            )),
+            Expr::Binary(left, BinOp::Or, right) => Cow::Owned(Value::Bool(
+                self.eval(left)?.is_truthy() || self.eval(right)?.is_truthy(),
+            )),
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (self.eval(left)?, self.eval(right)?);
+                if let Some(res) = arithmetic(*op, &left, &right) {
+                    return res.map(Cow::Owned);
+                }
+                let ordering = compare(&left, &right);
+                let res = match op {
+                    BinOp::Eq => ordering == Some(Ordering::Equal),
+                    BinOp::Ne => ordering != Some(Ordering::Equal),
+                    _ => {
+                        let ordering = ordering
+                            .ok_or_else(|| format!("can't compare {:?} with {:?}", left, right))?;
+                        match op {
+                            BinOp::Lt => ordering.is_lt(),
+                            BinOp::Le => ordering.is_le(),
+                            BinOp::Gt => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        }
+                    }
+                };
+                Cow::Owned(Value::Bool(res))
+            }
+        })
+    }
+}
+
+///The expressions are parsed when runtime templates are compiled.
+///Outputs of derived templates only keep their source.
+fn parsed_expr<'a, T>(parsed: &'a Option<T>, source: &str) -> Result<&'a T> {
+    parsed
+        .as_ref()
+        .ok_or_else(|| expr_error(source)("is only supported in derived templates".to_string()))
+}
+
+fn expr_error(expr: &str) -> impl Fn(String) -> RenderError + '_ {
+    move |message| RenderError::Expr {
+        expr: expr.to_string(),
+        message,
+    }
+}
+
+///The methods of values that runtime templates can call, named like
+///their Rust counterparts.
+fn call(value: &Value, method: &str, args: &[Cow<Value>]) -> Result<Value, String> {
+    let arg = args.first().map(|arg| &**arg);
+    Ok(match (method, value, arg) {
+        ("len", Value::Str(s), None) => Value::Int(s.len() as i64),
+        ("len", Value::List(items), None) => Value::Int(items.len() as i64),
+        ("len", Value::Map(map), None) => Value::Int(map.len() as i64),
+        ("is_empty", Value::Str(_) | Value::List(_) | Value::Map(_), None) => {
+            Value::Bool(!value.is_truthy())
+        }
+        ("to_uppercase", Value::Str(s), None) => Value::Str(s.to_uppercase()),
+        ("to_lowercase", Value::Str(s), None) => Value::Str(s.to_lowercase()),
+        ("trim", Value::Str(s), None) => Value::Str(s.trim().to_string()),
+        ("contains", Value::Str(s), Some(Value::Str(part))) => Value::Bool(s.contains(part)),
+        ("contains", Value::List(items), Some(item)) => Value::Bool(items.contains(item)),
+        ("contains_key", Value::Map(map), Some(Value::Str(key))) => {
+            Value::Bool(map.contains_key(key))
+        }
+        _ => return Err(format!("can't call `{}` on {:?}", method, value)),
+    })
+}
+
+///`+ - * / %` for numbers, `+` also joins strings. Returns `None` for
+///the other operators.
+fn arithmetic(op: BinOp, left: &Value, right: &Value) -> Option<Result<Value, String>> {
+    let symbol = match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Rem => "%",
+        _ => return None,
+    };
+    let float = |value: &Value| match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    };
+    Some(match (left, right) {
+        (Value::Str(a), Value::Str(b)) if op == BinOp::Add => Ok(Value::Str(format!("{}{}", a, b))),
+        (Value::Int(a), Value::Int(b)) => {
+            let res = match op {
+                BinOp::Add => a.checked_add(*b),
+                BinOp::Sub => a.checked_sub(*b),
+                BinOp::Mul => a.checked_mul(*b),
+                BinOp::Div => a.checked_div(*b),
+                _ => a.checked_rem(*b),
+            };
+            res.map(Value::Int)
+                .ok_or_else(|| format!("`{} {} {}` overflows or divides by zero", a, symbol, b))
+        }
+        _ => match (float(left), float(right)) {
+            (Some(a), Some(b)) => Ok(Value::Float(match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                _ => a % b,
+            })),
+            _ => Err(format!(
+                "can't apply `{}` to {:?} and {:?}",
+                symbol, left, right
+            )),
+        },
+    })
+}
+
+///Numbers are compared by value, no matter if they are ints or floats.
+///Other values can only be compared with values of the same kind.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{compile_source, RenderOptions};
+
+    fn render_str(source: &str, context: &Context) -> Result<String> {
+        render(&compile_source(source, &RenderOptions::default())?, context)
+    }
+
+    #[test]
+    fn renders_typed_values() {
+        let context = Context::new()
+            .with("name", "<Neo>")
+            .with("age", 37)
+            .with("height", 1.85)
+            .with("chosen", true)
+            .with("skills", vec!["kung fu", "flying"]);
+        let output = render_str(
+            "p {{name}}, {{age}}, {{height}}m, {{chosen}}\np title=\"{{skills}}\"",
+            &context,
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "<p>&lt;Neo&gt;, 37, 1.85m, true</p><p title=\"kung fu, flying\"></p>"
+        );
+    }
+
+    #[test]
+    fn does_not_render_markers_in_values() {
+        let context = Context::new().with("a", "{{b}}").with("b", "secret");
+        assert_eq!(render_str("p {{a}}", &context).unwrap(), "<p>{{b}}</p>");
+    }
+
+    #[test]
+    fn renders_conditions() {
+        let template = "- if user.admin && unread > 0\n  p Admin\n- else if user.name == \"Neo\"\n  p {{user.name}}\n- else\n  p Guest";
+        let user = |admin: bool, name: &str| {
+            let mut user = BTreeMap::new();
+            user.insert("admin", Value::from(admin));
+            user.insert("name", Value::from(name));
+            user
+        };
+        let context = Context::new()
+            .with("user", user(true, "Neo"))
+            .with("unread", 2);
+        assert_eq!(render_str(template, &context).unwrap(), "<p>Admin</p>");
+        let context = Context::new()
+            .with("user", user(false, "Neo"))
+            .with("unread", 2.5);
+        assert_eq!(render_str(template, &context).unwrap(), "<p>Neo</p>");
+        let context = Context::new().with("user", user(false, "Trinity"));
+        assert_eq!(render_str(template, &context).unwrap(), "<p>Guest</p>");
+    }
+
+    #[test]
+    fn treats_missing_paths_as_false() {
+        let template = "- if !user.nickname\n  p none";
+        assert_eq!(
+            render_str(template, &Context::new()).unwrap(),
+            "<p>none</p>"
+        );
+        let context = Context::new().with("name", "Neo");
+        let err = render_str("- if name > 1\n  p", &context).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Expr {
+                expr: "name > 1".to_string(),
+                message: "can't compare Str(\"Neo\") with Int(1)".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn renders_loops() {
+        let template = "ul\n  - for user in users\n    li class=\"{{loop.index}}\" {{user.name}}\n    - if loop.last\n      li end\n  - else\n    li Nobody";
+        let user = |name: &'static str| BTreeMap::from([("name", name)]);
+        let context = Context::new().with("users", vec![user("Neo"), user("<Trinity>")]);
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<ul><li class=\"1\">Neo</li><li class=\"2\">&lt;Trinity&gt;</li><li>end</li></ul>"
+        );
+        let context = Context::new().with("users", Vec::<Value>::new());
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<ul><li>Nobody</li></ul>"
+        );
+        let context = Context::new().with("users", Value::Null);
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<ul><li>Nobody</li></ul>"
+        );
+        assert_eq!(
+            render_str(template, &Context::new()).unwrap_err(),
+            RenderError::MissingVar("users".to_string())
+        );
+    }
+
+    #[test]
+    fn renders_nested_loops() {
+        let template = "- for row in rows\n  - for cell in row\n    b {{cell}}{{loop.index0}}\n  i {{loop.first}}";
+        let context = Context::new().with("rows", vec![vec!["a", "b"], vec!["c"]]);
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<b>a0</b><b>b1</b><i>true</i><b>c0</b><i>false</i>"
+        );
+        let err = render_str("- for (a, b) in rows\n  p", &context).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression `(a, b) in rows`: only a single name can be bound at line 1, column 1\n\
+             - for (a, b) in rows\n\
+             ^"
+        );
+    }
+
+    #[test]
+    fn rejects_derive_only_constructs() {
+        let err = render_str("- match a\n  - when _\n    p", &Context::new()).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(e) if e.line == 1));
+        let err = render_str("p\n- if let Some(a) = b\n  p", &Context::new()).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(e) if e.line == 2));
+    }
+
+    #[test]
+    fn reports_invalid_expressions_when_compiling() {
+        let template = "- if true\n  p x\n- else if a ==\n  p y";
+        let err = render_str(template, &Context::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid expression `a ==`: unexpected end of the expression at line 3, column 1\n\
+             - else if a ==\n\
+             ^"
+        );
+        let err = render_str("ul\n  - for i in items.\n    li", &Context::new()).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(e) if e.line == 2 && e.col == 3));
+    }
+
+    #[test]
+    fn reports_invalid_variables_when_compiling() {
+        let err = render_str("div\n  p {{ a + }}", &Context::new()).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(e) if e.line == 2 && e.col == 5));
+        for template in ["p title=\"{{ name | }}\"", "/! {{no way}}", "p {{{ .a }}}"] {
+            let err = render_str(template, &Context::new()).unwrap_err();
+            assert!(matches!(err, RenderError::Parse(_)), "{}", template);
+        }
+    }
+
+    #[test]
+    fn applies_filters() {
+        let context = Context::new()
+            .with("name", " Neo ")
+            .with("tags", vec!["one", "chosen"])
+            .with("user", BTreeMap::from([("q", "a&b")]))
+            .with_filter("shout", |value, args| {
+                Ok(format!("{}{}", value, args[0]).into())
+            });
+        let template = "p {{ name | trim | upper | shout(\"!\") }} {{ tags | join(\" & \") }} {{tags|length}}\n\
+                        p {{ nickname | default(\"Anon\") | truncate(2) }} {{ user | json }} {{ user.q | urlencode }}";
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<p>NEO! one &amp; chosen 2</p><p>An... {\"q\":\"a&amp;b\"} a%26b</p>"
+        );
+        let err = render_str("p {{ name | nope }}", &context).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Expr {
+                expr: "name | nope".to_string(),
+                message: "unknown filter `nope`".to_string()
+            }
+        );
+        let err = render_str("- for i in items\n  p {{ i | nope }}", &context).unwrap_err();
+        assert!(matches!(err, RenderError::Expr { expr, .. } if expr == "i | nope"));
+        let err = render_str("p {{ nickname | upper }}", &context).unwrap_err();
+        assert_eq!(err, RenderError::MissingVar("nickname".to_string()));
+    }
+
+    #[test]
+    fn evaluates_expressions() {
+        let context = Context::new()
+            .with("name", "Neo")
+            .with("tags", vec!["one", "chosen"])
+            .with("scores", BTreeMap::from([("neo", 20)]))
+            .with("height", 1.5);
+        let template = "p {{ tags.len() }} {{ tags[1] | upper }} {{ scores[\"neo\"] * 2 + 1 }} {{ height * 2 }}\n\
+                        p {{ name + \"!\" }} {{ name.to_lowercase() }} {{ tags.contains(\"one\") }} {{ 7 % 4 - 1 }}";
+        assert_eq!(
+            render_str(template, &context).unwrap(),
+            "<p>2 CHOSEN 41 3</p><p>Neo! neo true 2</p>"
+        );
+        let err = render_str("p {{ scores.neo / 0 }}", &context).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Expr {
+                expr: "scores.neo / 0".to_string(),
+                message: "`20 / 0` overflows or divides by zero".to_string()
+            }
+        );
+        let err = render_str("p {{ name.len(1) }}", &context).unwrap_err();
+        assert!(
+            matches!(err, RenderError::Expr { message, .. } if message.starts_with("can't call `len`"))
+        );
+    }
+
+    #[test]
+    fn reports_missing_vars() {
+        let err = render_str("p {{name}}", &Context::new()).unwrap_err();
+        assert_eq!(err, RenderError::MissingVar("name".to_string()));
+    }
+}
@@ -1,4 +1,10 @@
+pub mod context;
+pub mod escape;
+pub mod filters;
+pub mod interpreter;
+pub mod output;
 pub mod parser;
 pub mod renderer;
 pub mod template_manager;
 pub mod util;
+pub mod write;
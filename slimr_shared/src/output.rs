@@ -0,0 +1,363 @@
+use std::fmt;
+
+use crate::escape::{self, Escape};
+use crate::parser::expr::{self, Expr, Var};
+use crate::parser::text::Segment;
+
+///A rendered template. The html around the variables is already
+///rendered, the variables are filled in when the template is used.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Output {
+    parts: Vec<Part>,
+    ///The length of the static html plus the number of variables and conditions
+    len: usize,
+}
+
+///Expressions are kept as they are written in the template. Runtime
+///templates also keep them `parsed`, derived templates parse them as Rust.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    Static(String),
+    ///A variable that's escaped for the place where it's inserted
+    Var {
+        name: String,
+        escape: Escape,
+        parsed: Option<Var>,
+    },
+    ///The body of the first branch whose condition is true, or `otherwise`
+    If {
+        branches: Vec<Branch>,
+        otherwise: Option<Output>,
+    },
+    ///The body for every item, or `empty` if there are none
+    For {
+        pattern: String,
+        iter: String,
+        parsed: Option<Expr>,
+        body: Output,
+        empty: Option<Output>,
+    },
+    ///The body of the first arm whose pattern matches
+    Match {
+        expr: String,
+        arms: Vec<Arm>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    ///The condition as it's written in the template
+    pub cond: String,
+    pub parsed: Option<Expr>,
+    pub body: Output,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    ///The pattern and its guard, like `Some(n) if n > 3`
+    pub pattern: String,
+    pub body: Output,
+}
+
+impl Part {
+    ///The outputs nested in conditions, loops and matches.
+    pub fn bodies(&self) -> Vec<&Output> {
+        match self {
+            Part::Static(_) | Part::Var { .. } => vec![],
+            Part::If {
+                branches,
+                otherwise,
+            } => branches
+                .iter()
+                .map(|b| &b.body)
+                .chain(otherwise.as_ref())
+                .collect(),
+            Part::For { body, empty, .. } => std::iter::once(body).chain(empty.as_ref()).collect(),
+            Part::Match { arms, .. } => arms.iter().map(|a| &a.body).collect(),
+        }
+    }
+
+    pub fn bodies_mut(&mut self) -> Vec<&mut Output> {
+        match self {
+            Part::Static(_) | Part::Var { .. } => vec![],
+            Part::If {
+                branches,
+                otherwise,
+            } => branches
+                .iter_mut()
+                .map(|b| &mut b.body)
+                .chain(otherwise.as_mut())
+                .collect(),
+            Part::For { body, empty, .. } => std::iter::once(body).chain(empty.as_mut()).collect(),
+            Part::Match { arms, .. } => arms.iter_mut().map(|a| &mut a.body).collect(),
+        }
+    }
+}
+
+impl Output {
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    ///Grows with everything that's added, so comparing it tells
+    ///whether anything was added in between.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    ///Reserves space for the static html that follows.
+    pub fn reserve(&mut self, additional: usize) {
+        match self.parts.last_mut() {
+            Some(Part::Static(s)) => s.reserve(additional),
+            Some(_) => (),
+            None => self
+                .parts
+                .push(Part::Static(String::with_capacity(additional))),
+        }
+    }
+
+    ///The length of the static html, a lower bound of the rendered length.
+    pub fn static_len(&self) -> usize {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Static(s) => s.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    ///Adds html that's inserted as it is.
+    pub fn push_str(&mut self, html: &str) {
+        if html.is_empty() {
+            return;
+        }
+        self.len += html.len();
+        match self.parts.last_mut() {
+            Some(Part::Static(s)) => s.push_str(html),
+            _ => self.parts.push(Part::Static(html.to_string())),
+        }
+    }
+
+    ///Removes html from the end, if the output ends with it.
+    pub fn pop_str(&mut self, html: &str) -> bool {
+        match self.parts.last_mut() {
+            Some(Part::Static(s)) if s.ends_with(html) => {
+                s.truncate(s.len() - html.len());
+                self.len -= html.len();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn push(&mut self, part: Part) {
+        match part {
+            Part::Static(s) => self.push_str(&s),
+            other => {
+                self.len += 1;
+                self.parts.push(other);
+            }
+        }
+    }
+
+    pub fn append(&mut self, other: Output) {
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+        for part in other.parts {
+            self.push(part);
+        }
+    }
+
+    ///Changes the static html, including the html in conditions and loops.
+    pub fn map_static(self, f: &impl Fn(&str) -> String) -> Output {
+        let mut res = Output::default();
+        for mut part in self.parts {
+            match part {
+                Part::Static(html) => res.push_str(&f(&html)),
+                _ => {
+                    for body in part.bodies_mut() {
+                        *body = std::mem::take(body).map_static(f);
+                    }
+                    res.push(part);
+                }
+            }
+        }
+        res
+    }
+
+    ///Adds text of the template. The text and its variables are escaped
+    ///with `escape`, except for raw variables.
+    pub fn push_text(&mut self, segments: Vec<Segment>, escape: Escape) {
+        for segment in segments {
+            match segment {
+                Segment::Var(name) => self.push(Part::Var {
+                    name: name.to_string(),
+                    escape,
+                    parsed: expr::parse_var(name).ok(),
+                }),
+                Segment::Text(t) => {
+                    let mut escaped = String::with_capacity(t.len());
+                    escape::escape_static(&mut escaped, escape, t)
+                        .expect("writing to a String can't fail");
+                    self.push_str(&escaped);
+                }
+                other => self.push(other.into()),
+            }
+        }
+    }
+
+    ///Adds the text of a raw text element like `script`. Its text is
+    ///written as it is, its variables are still escaped.
+    pub fn push_raw_text(&mut self, segments: Vec<Segment>) {
+        for segment in segments {
+            match segment {
+                Segment::Text(t) => self.push_str(t),
+                other => self.push(other.into()),
+            }
+        }
+    }
+}
+
+impl<'a> From<Segment<'a>> for Part {
+    fn from(segment: Segment<'a>) -> Part {
+        match segment {
+            Segment::Text(t) => Part::Static(t.to_string()),
+            Segment::ParentVar(name) => Part::Static(format!("{{pv{{{}}}}}", name)),
+            Segment::Var(name) => Part::Var {
+                name: name.to_string(),
+                escape: Escape::Text,
+                parsed: expr::parse_var(name).ok(),
+            },
+            Segment::RawVar(name) => Part::Var {
+                name: name.to_string(),
+                escape: Escape::Raw,
+                parsed: expr::parse_var(name).ok(),
+            },
+        }
+    }
+}
+
+///Writes the variables back in template syntax. Conditions, loops and
+///matches are written like `{{#if cond}}…{{else}}…{{/if}}`, for debugging.
+impl fmt::Display for Part {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Part::Static(s) => write!(f, "{}", s),
+            Part::Var {
+                name,
+                escape: Escape::Raw,
+                ..
+            } => write!(f, "{{{{{{{}}}}}}}", name),
+            Part::Var { name, .. } => write!(f, "{{{{{}}}}}", name),
+            Part::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, branch) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "#if" } else { "else if" };
+                    write!(f, "{{{{{} {}}}}}{}", keyword, branch.cond, branch.body)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "{{{{else}}}}{}", otherwise)?;
+                }
+                write!(f, "{{{{/if}}}}")
+            }
+            Part::For {
+                pattern,
+                iter,
+                body,
+                empty,
+                ..
+            } => {
+                write!(f, "{{{{#for {} in {}}}}}{}", pattern, iter, body)?;
+                if let Some(empty) = empty {
+                    write!(f, "{{{{else}}}}{}", empty)?;
+                }
+                write!(f, "{{{{/for}}}}")
+            }
+            Part::Match { expr, arms } => {
+                write!(f, "{{{{#match {}}}}}", expr)?;
+                for arm in arms {
+                    write!(f, "{{{{when {}}}}}{}", arm.pattern, arm.body)?;
+                }
+                write!(f, "{{{{/match}}}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::text;
+
+    #[test]
+    fn splits_vars_from_html() {
+        let mut output = Output::default();
+        output.push_str("<p>");
+        output.push_text(text::segments("Hi {{name}}, {{{ html }}}"), Escape::Text);
+        output.push_str("</p>");
+        assert_eq!(
+            output.parts(),
+            &[
+                Part::Static("<p>Hi ".to_string()),
+                Part::Var {
+                    name: "name".to_string(),
+                    escape: Escape::Text,
+                    parsed: expr::parse_var("name").ok()
+                },
+                Part::Static(", ".to_string()),
+                Part::Var {
+                    name: "html".to_string(),
+                    escape: Escape::Raw,
+                    parsed: expr::parse_var("html").ok()
+                },
+                Part::Static("</p>".to_string()),
+            ]
+        );
+        assert_eq!(output.to_string(), "<p>Hi {{name}}, {{{html}}}</p>");
+        assert_eq!(output.static_len(), 12);
+    }
+
+    #[test]
+    fn escapes_static_text() {
+        let mut output = Output::default();
+        output.push_text(text::segments("a < b &amp; \"c\""), Escape::Text);
+        output.push_text(text::segments(" \"{{x}}\""), Escape::Attr);
+        assert_eq!(output.to_string(), "a &lt; b &amp; \"c\" &quot;{{x}}&quot;");
+    }
+
+    #[test]
+    fn counts_conditions() {
+        let mut body = Output::default();
+        body.push_str("<b>");
+        let mut output = Output::default();
+        output.push(Part::If {
+            branches: vec![Branch {
+                cond: "a".to_string(),
+                parsed: None,
+                body,
+            }],
+            otherwise: Some(Output::default()),
+        });
+        assert!(!output.is_empty());
+        assert_eq!(output.static_len(), 0);
+        assert_eq!(output.to_string(), "{{#if a}}<b>{{else}}{{/if}}");
+    }
+}
@@ -1,68 +1,342 @@
+use std::fmt;
+
+use pest::error::{Error as PestError, ErrorVariant, InputLocation};
 use pest::iterators::Pair;
 use pest::Parser;
 use pest_derive::Parser;
+use thiserror::Error;
 
 #[derive(Parser)]
 #[grammar = "parser/slimr.pest"]
 struct SHParser;
 
-use super::ast_types::{Attr, Cont, Elem, Include, Node};
+use super::ast_types::{
+    Attr, ChildSlot, Comment, CommentKind, Cont, ContLine, Elem, For, If, IfBranch, Include, Match,
+    MatchArm, Node, Parent, ParentVar, Span,
+};
+use super::indent::{self, Indent};
+use super::source_map::SourceMap;
+
+///Options that change how templates are parsed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    ///The unit of indentation. It's detected from the template by default.
+    pub indent: Indent,
+}
+
+pub fn from_str(input: &str) -> Result<Vec<Node>> {
+    from_str_with(input, &ParseOptions::default())
+}
+
+pub fn from_str_with(input: &str, options: &ParseOptions) -> Result<Vec<Node>> {
+    generate(input, options)
+}
+
+fn generate(input: &str, options: &ParseOptions) -> Result<Vec<Node>> {
+    let parsed = indent::normalize(input, options.indent)?;
+    let map = SourceMap::new(input, &parsed);
+    let parse_res =
+        SHParser::parse(Rule::html, &parsed.text).map_err(|e| ParseError::from_pest(e, &map))?;
+    let ast = gen_nodes(parse_res, &map)?;
+    check_layout(&ast, &map)?;
+    Ok(ast)
+}
+
+///Makes sure that the parent template and its variables are declared
+///once at the top level and that there is at most one child slot.
+fn check_layout(ast: &[Node], map: &SourceMap) -> Result<()> {
+    let error = |span: Span, msg: &str| {
+        ParseError::new(
+            span.line,
+            span.col,
+            ParseErrorKind::Other(msg.to_string()),
+            map.line(span.line),
+        )
+    };
+    let mut parent = None;
+    let mut vars = Vec::new();
+    for node in ast {
+        match node {
+            Node::PARENT(p) if parent.is_some() => {
+                return Err(error(p.span, "a template can only have one parent"))
+            }
+            Node::PARENT(p) => parent = Some(p),
+            Node::PV(var) if vars.contains(&&var.name) => {
+                return Err(error(var.span, "parent variable is assigned twice"))
+            }
+            Node::PV(var) => vars.push(&var.name),
+            _ => (),
+        }
+    }
+    let mut slots = Vec::new();
+    find_nested_layout_nodes(ast, false, &mut slots).map_err(|span| {
+        error(
+            span,
+            "`<!` and `<pv` can only be used at the top level of a template",
+        )
+    })?;
+    if let Some(span) = slots.get(1) {
+        return Err(error(*span, "`=>child` can only be used once per template"));
+    }
+    Ok(())
+}
+
+fn find_nested_layout_nodes(
+    ast: &[Node],
+    nested: bool,
+    slots: &mut Vec<Span>,
+) -> std::result::Result<(), Span> {
+    for node in ast {
+        match node {
+            Node::PARENT(_) | Node::PV(_) if nested => return Err(node.span()),
+            Node::CHILD(slot) => slots.push(slot.span),
+            _ => (),
+        }
+        for body in node.bodies() {
+            find_nested_layout_nodes(body, true, slots)?;
+        }
+    }
+    Ok(())
+}
 
-pub fn from_str(input: &str) -> Vec<Node> {
-    generate(input)
+///Generates a list of sibling nodes. Control lines that continue
+///the control line before them, like `- else`, are attached to it.
+fn gen_nodes<'a>(
+    pairs: impl Iterator<Item = Pair<'a, Rule>>,
+    map: &SourceMap,
+) -> Result<Vec<Node>> {
+    let mut nodes: Vec<Node> = Vec::new();
+    for pair in pairs {
+        if pair.as_rule() != Rule::ctrl_node {
+            nodes.push(gen_node(pair, map)?);
+            continue;
+        }
+        let span = map.span(pair.as_span());
+        let mut inner = pair.into_inner();
+        let ctrl = inner
+            .next()
+            .expect("control lines start with their keyword");
+        let rule = ctrl.as_rule();
+        if rule == Rule::match_ctrl {
+            nodes.push(Node::MATCH(gen_match(ctrl, inner, span, map)?));
+            continue;
+        }
+        let children = gen_nodes(inner, map)?;
+        let mut args = ctrl.into_inner().map(|e| e.as_str().trim().to_string());
+        let mut arg = || args.next().unwrap_or_default();
+        match rule {
+            Rule::if_ctrl => nodes.push(Node::IF(If {
+                branches: vec![IfBranch {
+                    cond: arg(),
+                    children,
+                    span,
+                }],
+                span,
+                ..If::default()
+            })),
+            Rule::for_ctrl => nodes.push(Node::FOR(For {
+                pattern: arg(),
+                iter: arg(),
+                children,
+                span,
+                ..For::default()
+            })),
+            rule => {
+                let previous = nodes.iter_mut().rev().find(|n| !is_silent(n));
+                match (previous, rule) {
+                    (Some(Node::IF(node)), Rule::else_if_ctrl) if node.otherwise.is_none() => {
+                        node.branches.push(IfBranch {
+                            cond: arg(),
+                            children,
+                            span,
+                        })
+                    }
+                    (Some(Node::IF(node)), Rule::else_ctrl) if node.otherwise.is_none() => {
+                        node.otherwise = Some(children)
+                    }
+                    (Some(Node::FOR(node)), Rule::else_ctrl) if node.empty.is_none() => {
+                        node.empty = Some(children)
+                    }
+                    (_, Rule::when_ctrl) => {
+                        return Err(ctrl_error(
+                            span,
+                            "`- when` needs to be inside a `- match`",
+                            map,
+                        ))
+                    }
+                    _ => {
+                        return Err(ctrl_error(
+                            span,
+                            "`- else` needs an `- if` or `- for` right before it",
+                            map,
+                        ))
+                    }
+                }
+            }
+        }
+    }
+    Ok(nodes)
 }
 
-fn generate(input: &str) -> Vec<Node> {
-    let mut ast: Vec<Node> = Vec::new();
-    //TODO make sure parse_res isn't empty or throw error
-    let parse_res = SHParser::parse(Rule::html, input).expect("unsuccessful parse");
-    for node in parse_res {
-        ast.push(gen_node(node))
+///Generates a `- match` and its `- when` arms. Silent comments
+///between the arms are dropped, everything else is an error.
+fn gen_match<'a>(
+    ctrl: Pair<'a, Rule>,
+    children: impl Iterator<Item = Pair<'a, Rule>>,
+    span: Span,
+    map: &SourceMap,
+) -> Result<Match> {
+    let mut node = Match {
+        expr: ctrl.into_inner().as_str().trim().to_string(),
+        span,
+        ..Match::default()
+    };
+    for pair in children {
+        let span = map.span(pair.as_span());
+        match pair.as_rule() {
+            Rule::comment_node if gen_comment(pair.clone(), map).is_silent() => continue,
+            Rule::ctrl_node => {
+                let mut inner = pair.into_inner();
+                if let Some(ctrl) = inner.next().filter(|c| c.as_rule() == Rule::when_ctrl) {
+                    node.arms.push(MatchArm {
+                        pattern: ctrl.into_inner().as_str().trim().to_string(),
+                        children: gen_nodes(inner, map)?,
+                        span,
+                    });
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        return Err(ctrl_error(
+            span,
+            "`- match` can only contain `- when` lines",
+            map,
+        ));
     }
-    ast
+    Ok(node)
+}
+
+fn ctrl_error(span: Span, message: &str, map: &SourceMap) -> ParseError {
+    ParseError::new(
+        span.line,
+        span.col,
+        ParseErrorKind::Other(message.to_string()),
+        map.line(span.line),
+    )
+}
+
+fn is_silent(node: &Node) -> bool {
+    matches!(node, Node::COMMENT(comment) if comment.is_silent())
 }
 
-fn gen_node(node: Pair<Rule>) -> Node {
-    match node.as_rule() {
-        Rule::el_node => Node::ELEM(gen_elem(node)),
-        Rule::include_node => Node::INCLUDE(gen_include(node)),
+fn gen_node(node: Pair<Rule>, map: &SourceMap) -> Result<Node> {
+    Ok(match node.as_rule() {
+        Rule::el_node => Node::ELEM(gen_elem(node, map)?),
+        Rule::include_node => Node::INCLUDE(gen_include(node, map)),
+        Rule::comment_node => Node::COMMENT(gen_comment(node, map)),
+        Rule::parent_node => Node::PARENT(gen_parent(node, map)),
+        Rule::pv_node => Node::PV(gen_parent_var(node, map)),
+        Rule::child_slot => Node::CHILD(ChildSlot {
+            span: map.span(node.as_span()),
+        }),
         _ => unreachable!(),
+    })
+}
+
+fn gen_parent(val: Pair<Rule>, map: &SourceMap) -> Parent {
+    let mut parent = Parent {
+        span: map.span(val.as_span()),
+        ..Parent::default()
+    };
+    for val in val.into_inner() {
+        match val.as_rule() {
+            Rule::include_path => parent.path = val.as_str().to_string(),
+            _ => unreachable!(),
+        }
     }
+    parent
 }
 
-fn gen_include(val: Pair<Rule>) -> Include {
-    let mut include = Include::default();
+fn gen_parent_var(val: Pair<Rule>, map: &SourceMap) -> ParentVar {
+    let mut var = ParentVar {
+        span: map.span(val.as_span()),
+        ..ParentVar::default()
+    };
     for val in val.into_inner() {
         match val.as_rule() {
-            Rule::include_path => {
-                include = Include {
-                    path: val.as_str().to_string(),
-                }
-            }
+            Rule::pv_name => var.name = val.as_str().to_string(),
+            Rule::cont_inline => var.value = gen_cont_line(val, map),
+            _ => unreachable!(),
+        }
+    }
+    var
+}
+
+fn gen_comment(val: Pair<Rule>, map: &SourceMap) -> Comment {
+    let mut comment = Comment {
+        span: map.span(val.as_span()),
+        ..Comment::default()
+    };
+    for val in val.into_inner() {
+        comment.kind = match val.as_rule() {
+            Rule::line_comment => CommentKind::LINE,
+            Rule::block_comment => CommentKind::BLOCK,
+            Rule::html_comment => CommentKind::HTML,
+            _ => unreachable!(),
+        };
+        if let Some(text) = val.into_inner().next() {
+            comment.text = text.as_str().to_string();
+        }
+    }
+    comment
+}
+
+fn gen_include(val: Pair<Rule>, map: &SourceMap) -> Include {
+    let mut include = Include {
+        span: map.span(val.as_span()),
+        ..Include::default()
+    };
+    for val in val.into_inner() {
+        match val.as_rule() {
+            Rule::include_path => include.path = val.as_str().to_string(),
             _ => unreachable!(),
         }
     }
     include
 }
 
-fn gen_elem(val: Pair<Rule>) -> Elem {
-    let mut new_elem = Elem::default();
+fn gen_elem(val: Pair<Rule>, map: &SourceMap) -> Result<Elem> {
+    let mut new_elem = Elem {
+        span: map.span(val.as_span()),
+        ..Elem::default()
+    };
+    let mut children = Vec::new();
     for val in val.into_inner() {
         match val.as_rule() {
             Rule::tag => new_elem.tag = String::from(val.as_str()),
-            Rule::class_name => add_class(&mut new_elem, &val.as_str()),
+            Rule::class_name => add_class(&mut new_elem, val.as_str()),
             Rule::id_name => new_elem.id = Some(String::from(val.as_str())),
-            Rule::attr => add_attr(&mut new_elem, val),
-            Rule::cont_inline => new_elem.cont = Some(Cont::LINE(String::from(val.as_str()))),
-            Rule::cont_block_line => add_cont_block_line(&mut new_elem, &val.as_str()),
-            Rule::el_node => add_child_elems(&mut new_elem, val),
+            Rule::attr => add_attr(&mut new_elem, val, map),
+            Rule::cont_inline => new_elem.cont = Some(Cont::LINE(gen_cont_line(val, map))),
+            Rule::cont_block_line => add_cont_block_line(&mut new_elem, val, map),
+            Rule::el_node
+            | Rule::include_node
+            | Rule::comment_node
+            | Rule::parent_node
+            | Rule::pv_node
+            | Rule::child_slot
+            | Rule::ctrl_node => children.push(val),
             _ => unreachable!(),
         }
     }
-    new_elem
+    if !children.is_empty() {
+        new_elem.children = Some(gen_nodes(children.into_iter(), map)?);
+    }
+    Ok(new_elem)
 }
 
-fn add_class<'a>(elem: &mut Elem, val: &'a str) {
+fn add_class(elem: &mut Elem, val: &str) {
     match elem.classes {
         Some(ref mut vec) => {
             vec.push(val.to_string());
@@ -71,8 +345,11 @@ fn add_class<'a>(elem: &mut Elem, val: &'a str) {
     }
 }
 
-fn add_attr(elem: &mut Elem, val: Pair<Rule>) {
-    let mut attr = Attr::default();
+fn add_attr(elem: &mut Elem, val: Pair<Rule>, map: &SourceMap) {
+    let mut attr = Attr {
+        span: map.span(val.as_span()),
+        ..Attr::default()
+    };
     for v in val.into_inner() {
         match v.as_rule() {
             Rule::attr_name => attr.name.push_str(v.as_str()),
@@ -88,35 +365,135 @@ fn add_attr(elem: &mut Elem, val: Pair<Rule>) {
         None => elem.attr = Some(vec![attr]),
     }
 }
-fn add_cont_block_line<'a>(elem: &mut Elem, val: &'a str) {
+
+fn gen_cont_line(val: Pair<Rule>, map: &SourceMap) -> ContLine {
+    ContLine {
+        text: val.as_str().to_string(),
+        span: map.span(val.as_span()),
+    }
+}
+
+fn add_cont_block_line(elem: &mut Elem, val: Pair<Rule>, map: &SourceMap) {
+    let line = gen_cont_line(val, map);
     match elem.cont {
         Some(ref mut cont_enum) => match cont_enum {
-            Cont::BLOCK(cont) => cont.push(val.to_string()),
+            Cont::BLOCK(cont) => cont.push(line),
             _ => unreachable!(),
         },
-        None => elem.cont = Some(Cont::BLOCK(vec![val.to_string()])),
+        None => elem.cont = Some(Cont::BLOCK(vec![line])),
     }
 }
 
-fn add_child_elems<'a>(elem: &mut Elem, val: Pair<'a, Rule>) {
-    match elem.children {
-        Some(ref mut vec) => {
-            vec.push(gen_node(val));
+type Result<T, E = ParseError> = std::result::Result<T, E>;
+
+/// A template that couldn't be parsed.
+/// Lines and columns start at 1, like in an editor.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub kind: ParseErrorKind,
+    /// The source line the error occurred in.
+    pub snippet: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, col: usize, kind: ParseErrorKind, snippet: &str) -> ParseError {
+        ParseError {
+            line,
+            col,
+            kind,
+            snippet: snippet.to_string(),
         }
-        None => elem.children = Some(vec![gen_node(val)]),
     }
 }
 
+///Prints the error with the offending line and a marker below the column
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{kind} at line {line}, column {col}\n{snippet}\n{marker:>col$}",
+            kind = self.kind,
+            line = self.line,
+            col = self.col,
+            snippet = self.snippet,
+            marker = '^'
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The parser expected one of these grammar rules
+    Expected(Vec<String>),
+    /// The parser found input that isn't allowed at this position
+    Unexpected(Vec<String>),
+    /// A line's indentation doesn't fit the indentation unit
+    Indentation(String),
+    Other(String),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Expected(rules) => write!(f, "expected {}", rules.join(" or ")),
+            ParseErrorKind::Unexpected(rules) => write!(f, "unexpected {}", rules.join(" or ")),
+            ParseErrorKind::Indentation(msg) => write!(f, "{}", msg),
+            ParseErrorKind::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ParseError {
+    fn from_pest(err: PestError<Rule>, map: &SourceMap) -> Self {
+        let (line, col) = match err.location {
+            InputLocation::Pos(pos) => map.line_col(pos),
+            InputLocation::Span((start, _)) => map.line_col(start),
+        };
+        let kind = match &err.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => {
+                if positives.is_empty() && !negatives.is_empty() {
+                    ParseErrorKind::Unexpected(rule_names(negatives))
+                } else if positives.is_empty() {
+                    ParseErrorKind::Other("unexpected input".to_string())
+                } else {
+                    ParseErrorKind::Expected(rule_names(positives))
+                }
+            }
+            ErrorVariant::CustomError { message } => ParseErrorKind::Other(message.clone()),
+        };
+        ParseError::new(line, col, kind, map.line(line))
+    }
+}
+
+fn rule_names(rules: &[Rule]) -> Vec<String> {
+    rules.iter().map(|r| format!("{:?}", r)).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{node_el_vec, string_vec};
 
+    ///Parses the input and resets the spans, so the nodes can be compared
+    ///with nodes that are built by hand.
+    fn parse(input: &str) -> Vec<Node> {
+        strip(from_str(input).unwrap())
+    }
+
+    fn strip(nodes: Vec<Node>) -> Vec<Node> {
+        nodes.into_iter().map(Node::without_spans).collect()
+    }
+
     ///Helper macro to test a string against the expected output of elements
     ///Specify the expected elements inside []
     macro_rules! test_str_elems {
         ($input:literal, [ $($expected:expr),+ ]) => {
-            let output = from_str($input);
+            let output = parse($input);
             assert_eq!(output, vec![$(Node::ELEM($expected)),+]);
         }
     }
@@ -251,7 +628,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great".to_string()
+                    value: "great".to_string(),
+                    ..Attr::default()
                 }]
             )]
         );
@@ -265,7 +643,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "Mr. \"Anderson\"".to_string()
+                    value: "Mr. \"Anderson\"".to_string(),
+                    ..Attr::default()
                 }]
             )]
         );
@@ -279,7 +658,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great".to_string()
+                    value: "great".to_string(),
+                    ..Attr::default()
                 }]
             )]
         );
@@ -293,7 +673,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great".to_string()
+                    value: "great".to_string(),
+                    ..Attr::default()
                 }],
                 node_el_vec![Elem::from_ta("today")]
             )]
@@ -308,7 +689,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great".to_string()
+                    value: "great".to_string(),
+                    ..Attr::default()
                 }],
                 string_vec!["good", "morning"]
             )]
@@ -323,7 +705,8 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great day".to_string()
+                    value: "great day".to_string(),
+                    ..Attr::default()
                 }],
                 string_vec!["good", "morning"]
             )]
@@ -332,12 +715,448 @@ mod tests {
 
     #[test]
     fn parses_include() {
-        let output = from_str(">my/include");
+        let output = parse(">my/include");
         assert_eq!(
             output,
             vec![Node::INCLUDE(Include {
-                path: "my/include".into()
+                path: "my/include".into(),
+                ..Include::default()
+            })]
+        );
+    }
+
+    #[test]
+    fn returns_error_with_position() {
+        let err = from_str("div\n  p hello\n  span#").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.col, 8);
+        assert_eq!(err.snippet, "  span#");
+        assert_eq!(err.kind, ParseErrorKind::Expected(string_vec!["id_name"]));
+    }
+
+    #[test]
+    fn displays_error_with_marker() {
+        let err = from_str("h1#").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected id_name at line 1, column 4\nh1#\n   ^"
+        );
+    }
+
+    #[test]
+    fn records_spans_of_nodes() {
+        let output = from_str("div\n  p.big lang=en hello\n>nav").unwrap();
+        let div = match &output[0] {
+            Node::ELEM(el) => el,
+            _ => panic!("expected an element"),
+        };
+        assert_eq!((div.span.line, div.span.col, div.span.start), (1, 1, 0));
+        let p = match &div.children.as_ref().unwrap()[0] {
+            Node::ELEM(el) => el,
+            _ => panic!("expected an element"),
+        };
+        assert_eq!((p.span.line, p.span.col, p.span.start), (2, 3, 6));
+        let attr = &p.attr.as_ref().unwrap()[0];
+        assert_eq!((attr.span.start, attr.span.end, attr.span.col), (12, 19, 9));
+        match &p.cont {
+            Some(Cont::LINE(line)) => assert_eq!((line.span.start, line.span.col), (20, 17)),
+            _ => panic!("expected inline content"),
+        }
+        let include = output[1].span();
+        assert_eq!((include.line, include.col, include.start), (3, 1, 26));
+    }
+
+    #[test]
+    fn records_spans_of_block_lines() {
+        let output = from_str("p:\n  good\n  day").unwrap();
+        match &output[0] {
+            Node::ELEM(Elem {
+                cont: Some(Cont::BLOCK(lines)),
+                ..
+            }) => {
+                assert_eq!((lines[0].span.line, lines[0].span.col), (2, 3));
+                assert_eq!((lines[1].span.start, lines[1].span.end), (12, 15));
+            }
+            _ => panic!("expected a content block"),
+        }
+    }
+
+    #[test]
+    fn parses_tab_indentation() {
+        let output = strip(
+            from_str_with(
+                "hello\n\tworld\n\t\ttoday",
+                &ParseOptions {
+                    indent: Indent::Tabs,
+                },
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            output,
+            node_el_vec![Elem::from_ta_ch(
+                "hello",
+                node_el_vec![Elem::from_ta_ch(
+                    "world",
+                    node_el_vec![Elem::from_ta("today")]
+                )]
+            )]
+        );
+    }
+
+    #[test]
+    fn detects_four_space_indentation() {
+        test_str_elems!(
+            "hello\n    world\n    today",
+            [Elem::from_ta_ch(
+                "hello",
+                node_el_vec![Elem::from_ta("world"), Elem::from_ta("today")]
+            )]
+        );
+    }
+
+    #[test]
+    fn reports_errors_in_original_columns() {
+        let err = from_str("div\n\tp\n\t\tspan#").unwrap_err();
+        assert_eq!((err.line, err.col), (3, 8));
+        assert_eq!(err.snippet, "\t\tspan#");
+    }
+
+    #[test]
+    fn rejects_mixed_indentation() {
+        let err = from_str("div\n    p\n\tspan").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(matches!(err.kind, ParseErrorKind::Indentation(_)));
+    }
+
+    fn comment(kind: CommentKind, text: &str) -> Node {
+        Node::COMMENT(Comment {
+            kind,
+            text: text.to_string(),
+            ..Comment::default()
+        })
+    }
+
+    #[test]
+    fn parses_comment_lines() {
+        let output = parse("// hello\n/! world\n/* good\n   morning */\ndiv");
+        assert_eq!(
+            output,
+            vec![
+                comment(CommentKind::LINE, " hello"),
+                comment(CommentKind::HTML, "world"),
+                comment(CommentKind::BLOCK, " good\n   morning "),
+                Node::ELEM(Elem::from_ta("div"))
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_comment_children() {
+        let output = parse("div\n  //hello\n  p");
+        assert_eq!(
+            output,
+            node_el_vec![Elem::from_ta_ch(
+                "div",
+                vec![
+                    comment(CommentKind::LINE, "hello"),
+                    Node::ELEM(Elem::from_ta("p"))
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn drops_trailing_comments() {
+        test_str_elems!(
+            "h1 Hello World // greeting\ndiv /* a\n        b */\n  br",
+            [
+                Elem::from_ta_col("h1", "Hello World"),
+                Elem::from_ta_ch("div", node_el_vec![Elem::from_ta("br")])
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_slashes_in_content() {
+        test_str_elems!(
+            "p see http://example.com",
+            [Elem::from_ta_col("p", "see http://example.com")]
+        );
+    }
+
+    #[test]
+    fn keeps_unclosed_block_comments_as_content() {
+        test_str_elems!(
+            "p a /* b\n    span",
+            [Elem {
+                cont: Some(Cont::LINE("a /* b".into())),
+                ..Elem::from_ta_ch("p", node_el_vec![Elem::from_ta("span")])
+            }]
+        );
+        test_str_elems!(
+            "a href=\"http://x\" // c\n    span",
+            [Elem::from_ta_at_ch(
+                "a",
+                vec![Attr {
+                    name: "href".into(),
+                    value: "http://x".into(),
+                    ..Attr::default()
+                }],
+                node_el_vec![Elem::from_ta("span")]
+            )]
+        );
+    }
+
+    #[test]
+    fn parses_include_with_comment() {
+        let output = parse(">navbar // the navigation\ndiv");
+        assert_eq!(
+            output,
+            vec![
+                Node::INCLUDE(Include {
+                    path: "navbar".into(),
+                    ..Include::default()
+                }),
+                Node::ELEM(Elem::from_ta("div"))
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_layout_nodes() {
+        let output = parse("<!layouts/main // the parent\n<pv title Awesome Page\ndiv\n  =>child");
+        assert_eq!(
+            output,
+            vec![
+                Node::PARENT(Parent {
+                    path: "layouts/main".into(),
+                    ..Parent::default()
+                }),
+                Node::PV(ParentVar {
+                    name: "title".into(),
+                    value: "Awesome Page".into(),
+                    ..ParentVar::default()
+                }),
+                Node::ELEM(Elem::from_ta_ch(
+                    "div",
+                    vec![Node::CHILD(ChildSlot::default())]
+                ))
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_nested_parent_vars() {
+        let err = from_str("div\n  <pv title Hello").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 3));
+    }
+
+    #[test]
+    fn rejects_second_child_slot() {
+        let err = from_str("=>child\ndiv\n  =>child").unwrap_err();
+        assert_eq!((err.line, err.col), (3, 3));
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Other("`=>child` can only be used once per template".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_second_parent() {
+        let err = from_str("<!one\n<!two").unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn parses_namespaced_and_mixed_case_names() {
+        test_str_elems!(
+            "svg:linearGradient xlink:href=\"#a\" viewBox=0\nmy-element",
+            [
+                Elem::from_ta_at(
+                    "svg:linearGradient",
+                    vec![
+                        Attr {
+                            name: "xlink:href".to_string(),
+                            value: "#a".to_string(),
+                            ..Attr::default()
+                        },
+                        Attr {
+                            name: "viewBox".to_string(),
+                            value: "0".to_string(),
+                            ..Attr::default()
+                        }
+                    ]
+                ),
+                Elem::from_ta("my-element")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_colon_of_blocks() {
+        test_str_elems!(
+            "svg:text:\n  hello",
+            [Elem::from_ta_cob("svg:text", string_vec!["hello"])]
+        );
+    }
+
+    #[test]
+    fn parses_vars_in_ids_classes_and_naked_values() {
+        test_str_elems!(
+            "li#item-{{id}}.{{ kind }}.x{{{raw}}} href={{url}}",
+            [Elem {
+                tag: "li".to_string(),
+                id: Some("item-{{id}}".to_string()),
+                classes: Some(string_vec!["{{ kind }}", "x{{{raw}}}"]),
+                attr: Some(vec![Attr {
+                    name: "href".to_string(),
+                    value: "{{url}}".to_string(),
+                    ..Attr::default()
+                }]),
+                ..Elem::default()
+            }]
+        );
+    }
+
+    fn if_node(branches: Vec<(&str, Vec<Node>)>, otherwise: Option<Vec<Node>>) -> Node {
+        Node::IF(If {
+            branches: branches
+                .into_iter()
+                .map(|(cond, children)| IfBranch {
+                    cond: cond.to_string(),
+                    children,
+                    ..IfBranch::default()
+                })
+                .collect(),
+            otherwise,
+            ..If::default()
+        })
+    }
+
+    #[test]
+    fn parses_if_else() {
+        let input = "- if user.is_admin() && count > 0 // admins only
+  p Admin
+- else if guest
+  p Guest
+// silent comments don't break the chain
+- else
+  p User
+  p Welcome";
+        let output = parse(input);
+        assert_eq!(
+            output,
+            vec![
+                if_node(
+                    vec![
+                        (
+                            "user.is_admin() && count > 0",
+                            node_el_vec![Elem::from_ta_col("p", "Admin")]
+                        ),
+                        ("guest", node_el_vec![Elem::from_ta_col("p", "Guest")])
+                    ],
+                    Some(node_el_vec![
+                        Elem::from_ta_col("p", "User"),
+                        Elem::from_ta_col("p", "Welcome")
+                    ])
+                ),
+                Node::COMMENT(Comment {
+                    kind: CommentKind::LINE,
+                    text: " silent comments don't break the chain".to_string(),
+                    ..Comment::default()
+                })
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_nested_ifs() {
+        let output = parse("ul\n  - if a\n    - if b \n      li b\n  li c");
+        assert_eq!(
+            output,
+            node_el_vec![Elem::from_ta_ch(
+                "ul",
+                vec![
+                    if_node(
+                        vec![(
+                            "a",
+                            vec![if_node(
+                                vec![("b", node_el_vec![Elem::from_ta_col("li", "b")])],
+                                None
+                            )]
+                        )],
+                        None
+                    ),
+                    Node::ELEM(Elem::from_ta_col("li", "c"))
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_else_without_if() {
+        let err = from_str("p\n- else\n  p").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 1));
+        let err = from_str("- if a\n- else\n- else").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Other(
+                "`- else` needs an `- if` or `- for` right before it".to_string()
+            )
+        );
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn parses_for_else() {
+        let input = "ul\n  - for (i, item) in items.iter().enumerate() // rows\n    li {{item}}\n  - else\n    li None";
+        let output = parse(input);
+        assert_eq!(
+            output,
+            node_el_vec![Elem::from_ta_ch(
+                "ul",
+                vec![Node::FOR(For {
+                    pattern: "(i, item)".to_string(),
+                    iter: "items.iter().enumerate()".to_string(),
+                    children: node_el_vec![Elem::from_ta_col("li", "{{item}}")],
+                    empty: Some(node_el_vec![Elem::from_ta_col("li", "None")]),
+                    ..For::default()
+                })]
+            )]
+        );
+        assert!(from_str("- for a in b\n  p\n- else if c\n  p").is_err());
+    }
+
+    #[test]
+    fn parses_match() {
+        let input = "- match status.kind()\n  - when Status::Banned { reason } if !reason.is_empty()\n    p {{reason}}\n  // the rest\n  - when _\n    p Active";
+        let output = parse(input);
+        assert_eq!(
+            output,
+            vec![Node::MATCH(Match {
+                expr: "status.kind()".to_string(),
+                arms: vec![
+                    MatchArm {
+                        pattern: "Status::Banned { reason } if !reason.is_empty()".to_string(),
+                        children: node_el_vec![Elem::from_ta_col("p", "{{reason}}")],
+                        ..MatchArm::default()
+                    },
+                    MatchArm {
+                        pattern: "_".to_string(),
+                        children: node_el_vec![Elem::from_ta_col("p", "Active")],
+                        ..MatchArm::default()
+                    }
+                ],
+                ..Match::default()
             })]
         );
+        let err = from_str("- match a\n  p\n  - when _\n    p").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 3));
+        let err = from_str("- when _\n  p").unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Other("`- when` needs to be inside a `- match`".to_string())
+        );
     }
 }
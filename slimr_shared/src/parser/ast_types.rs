@@ -1,9 +1,24 @@
 use std::fmt;
 
+use crate::escape::{self, Escape};
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Node {
     ELEM(Elem),
     INCLUDE(Include),
+    COMMENT(Comment),
+    ///`<!path`, the parent template this template is rendered into
+    PARENT(Parent),
+    ///`<pv name value`, assigns a variable of the parent template
+    PV(ParentVar),
+    ///`=>child`, where the child template is rendered into this one
+    CHILD(ChildSlot),
+    ///`- if cond` with its `- else if cond` and `- else` branches
+    IF(If),
+    ///`- for pattern in iter` with its `- else` branch
+    FOR(For),
+    ///`- match expr` with its `- when pattern` arms
+    MATCH(Match),
 }
 
 #[non_exhaustive] //TODO remove once stable
@@ -15,43 +30,192 @@ pub struct Elem {
     pub attr: Option<Vec<Attr>>,
     pub cont: Option<Cont>,
     pub children: Option<Vec<Node>>,
+    pub span: Span,
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Clone)]
 pub struct Include {
     pub path: String,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct Parent {
+    pub path: String,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct ParentVar {
+    pub name: String,
+    pub value: ContLine,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct ChildSlot {
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct If {
+    pub branches: Vec<IfBranch>,
+    ///The children of `- else`
+    pub otherwise: Option<Vec<Node>>,
+    pub span: Span,
+}
+
+///A condition and the nodes that are rendered if it's true.
+///The condition is a Rust expression in derived templates.
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct IfBranch {
+    pub cond: String,
+    pub children: Vec<Node>,
+    pub span: Span,
+}
+
+///Renders its children for every item. The pattern binds the item,
+///like `user` or `(id, user)` in derived templates.
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct For {
+    pub pattern: String,
+    pub iter: String,
+    pub children: Vec<Node>,
+    ///The children of `- else`, rendered if there are no items
+    pub empty: Option<Vec<Node>>,
+    pub span: Span,
+}
+
+///Only supported in derived templates, where it becomes a Rust `match`.
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct Match {
+    pub expr: String,
+    pub arms: Vec<MatchArm>,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct MatchArm {
+    ///The pattern and its guard, like `Some(n) if n > 3`
+    pub pattern: String,
+    pub children: Vec<Node>,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct Comment {
+    pub kind: CommentKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CommentKind {
+    ///`// text`, dropped from the output
+    #[default]
+    LINE,
+    ///`/* text */`, dropped from the output
+    BLOCK,
+    ///`/! text`, rendered as `<!--text-->`
+    HTML,
 }
 
 #[derive(Default, PartialEq, Eq, Debug, Clone)]
 pub struct Attr {
     pub name: String,
     pub value: String,
+    pub span: Span,
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Cont {
-    LINE(String),
-    BLOCK(Vec<String>),
+    LINE(ContLine),
+    BLOCK(Vec<ContLine>),
+}
+
+#[derive(Default, PartialEq, Eq, Debug, Clone)]
+pub struct ContLine {
+    pub text: String,
+    pub span: Span,
+}
+
+///The position of a node in the template source.
+///`start` and `end` are byte offsets, `line` and `col` point
+///to the start and begin at 1.
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Node {
+    pub fn span(&self) -> Span {
+        match self {
+            Node::ELEM(elem) => elem.span,
+            Node::INCLUDE(include) => include.span,
+            Node::COMMENT(comment) => comment.span,
+            Node::PARENT(parent) => parent.span,
+            Node::PV(var) => var.span,
+            Node::CHILD(slot) => slot.span,
+            Node::IF(node) => node.span,
+            Node::FOR(node) => node.span,
+            Node::MATCH(node) => node.span,
+        }
+    }
+
+    ///The lists of nodes nested in this node, like the children
+    ///of an element or the branches of an `- if`.
+    pub fn bodies(&self) -> Vec<&[Node]> {
+        match self {
+            Node::ELEM(elem) => elem.children.as_deref().into_iter().collect(),
+            Node::IF(node) => node
+                .branches
+                .iter()
+                .map(|b| b.children.as_slice())
+                .chain(node.otherwise.as_deref())
+                .collect(),
+            Node::FOR(node) => std::iter::once(node.children.as_slice())
+                .chain(node.empty.as_deref())
+                .collect(),
+            Node::MATCH(node) => node.arms.iter().map(|a| a.children.as_slice()).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl Comment {
+    pub fn is_silent(&self) -> bool {
+        self.kind != CommentKind::HTML
+    }
+}
+
+impl From<&str> for ContLine {
+    fn from(text: &str) -> ContLine {
+        ContLine {
+            text: text.to_string(),
+            ..ContLine::default()
+        }
+    }
 }
 
 impl fmt::Display for Attr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.value.contains('\"') {
-            write!(f, r#"{name}='{val}'"#, name = self.name, val = self.value)
-        } else {
-            write!(f, r#"{name}="{val}""#, name = self.name, val = self.value)
-        }
+        write!(f, r#"{}=""#, self.name)?;
+        escape::escape_static(f, Escape::Attr, &self.value)?;
+        write!(f, "\"")
     }
 }
 
 impl fmt::Display for Cont {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Cont::LINE(text) => write!(f, "{}", text),
-            Cont::BLOCK(texts) => {
+            Cont::LINE(line) => write!(f, "{}", line.text),
+            Cont::BLOCK(lines) => {
                 let mut res = String::new();
-                for t in texts {
-                    res.push_str(t);
+                for l in lines {
+                    res.push_str(&l.text);
                     res.push('\n');
                 }
                 write!(f, "{}", res)
@@ -60,9 +224,92 @@ impl fmt::Display for Cont {
     }
 }
 
+#[cfg(test)]
+impl Node {
+    ///Resets the spans of the node and everything inside it, so parsed
+    ///nodes can be compared with nodes that are built by hand.
+    pub fn without_spans(self) -> Node {
+        let strip = |nodes: Vec<Node>| nodes.into_iter().map(Node::without_spans).collect();
+        let strip_line = |line: ContLine| ContLine {
+            span: Span::default(),
+            ..line
+        };
+        match self {
+            Node::ELEM(elem) => Node::ELEM(Elem {
+                attr: elem.attr.map(|attrs| {
+                    attrs
+                        .into_iter()
+                        .map(|attr| Attr {
+                            span: Span::default(),
+                            ..attr
+                        })
+                        .collect()
+                }),
+                cont: elem.cont.map(|cont| match cont {
+                    Cont::LINE(line) => Cont::LINE(strip_line(line)),
+                    Cont::BLOCK(lines) => Cont::BLOCK(lines.into_iter().map(strip_line).collect()),
+                }),
+                children: elem.children.map(strip),
+                span: Span::default(),
+                ..elem
+            }),
+            Node::INCLUDE(include) => Node::INCLUDE(Include {
+                span: Span::default(),
+                ..include
+            }),
+            Node::COMMENT(comment) => Node::COMMENT(Comment {
+                span: Span::default(),
+                ..comment
+            }),
+            Node::PARENT(parent) => Node::PARENT(Parent {
+                span: Span::default(),
+                ..parent
+            }),
+            Node::PV(var) => Node::PV(ParentVar {
+                value: strip_line(var.value),
+                span: Span::default(),
+                ..var
+            }),
+            Node::CHILD(_) => Node::CHILD(ChildSlot::default()),
+            Node::IF(node) => Node::IF(If {
+                branches: node
+                    .branches
+                    .into_iter()
+                    .map(|branch| IfBranch {
+                        children: strip(branch.children),
+                        span: Span::default(),
+                        ..branch
+                    })
+                    .collect(),
+                otherwise: node.otherwise.map(strip),
+                span: Span::default(),
+            }),
+            Node::FOR(node) => Node::FOR(For {
+                children: strip(node.children),
+                empty: node.empty.map(strip),
+                span: Span::default(),
+                ..node
+            }),
+            Node::MATCH(node) => Node::MATCH(Match {
+                arms: node
+                    .arms
+                    .into_iter()
+                    .map(|arm| MatchArm {
+                        children: strip(arm.children),
+                        span: Span::default(),
+                        ..arm
+                    })
+                    .collect(),
+                span: Span::default(),
+                ..node
+            }),
+        }
+    }
+}
+
 ///Implement some helpers for testing
 #[cfg(test)]
-impl<'a> Elem {
+impl Elem {
     ///Creates an element from a tag
     pub fn from_ta(tag: &str) -> Elem {
         Elem {
@@ -75,7 +322,7 @@ impl<'a> Elem {
     pub fn from_ta_col(tag: &str, cont: &str) -> Elem {
         Elem {
             tag: String::from(tag),
-            cont: Some(Cont::LINE(cont.to_string())),
+            cont: Some(Cont::LINE(cont.into())),
             ..Elem::default()
         }
     }
@@ -84,7 +331,9 @@ impl<'a> Elem {
     pub fn from_ta_cob(tag: &str, cont: Vec<String>) -> Elem {
         Elem {
             tag: String::from(tag),
-            cont: Some(Cont::BLOCK(cont)),
+            cont: Some(Cont::BLOCK(
+                cont.iter().map(|l| l.as_str().into()).collect(),
+            )),
             ..Elem::default()
         }
     }
@@ -133,7 +382,9 @@ impl<'a> Elem {
     ) -> Elem {
         Elem {
             tag: String::from(tag),
-            cont: Some(Cont::BLOCK(content)),
+            cont: Some(Cont::BLOCK(
+                content.iter().map(|l| l.as_str().into()).collect(),
+            )),
             classes: Some(classes),
             id: Some(String::from(id)),
             ..Elem::default()
@@ -144,7 +395,9 @@ impl<'a> Elem {
     pub fn from_ta_at_cob(tag: &str, attributes: Vec<Attr>, content: Vec<String>) -> Elem {
         Elem {
             tag: String::from(tag),
-            cont: Some(Cont::BLOCK(content)),
+            cont: Some(Cont::BLOCK(
+                content.iter().map(|l| l.as_str().into()).collect(),
+            )),
             attr: Some(attributes),
             ..Elem::default()
         }
@@ -168,5 +421,3 @@ impl<'a> Elem {
         }
     }
 }
-
-
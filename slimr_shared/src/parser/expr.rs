@@ -0,0 +1,468 @@
+use crate::context::Value;
+
+///An expression of a template that's rendered at runtime, like a
+///condition or a variable. Derived templates parse the same source
+///as a Rust expression instead.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Lit(Value),
+    ///A dotted path into the context, like `user.is_admin`
+    Path(String),
+    ///`value[index]`, also used for fields of computed values
+    Index(Box<Expr>, Box<Expr>),
+    ///`value.method(args)`
+    Call(Box<Expr>, String, Vec<Expr>),
+    Not(Box<Expr>),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+///A variable like `{{ name | truncate(20) | upper }}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Var {
+    ///The source of the expression, without the filters
+    pub source: String,
+    pub expr: Expr,
+    ///Applied from left to right
+    pub filters: Vec<Filter>,
+}
+
+///A filter and its arguments. The arguments are literals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub name: String,
+    pub args: Vec<Value>,
+}
+
+///Splits a variable into its expression and its filters.
+pub fn parse_var(input: &str) -> Result<Var, String> {
+    let mut parser = Parser { input, pos: 0 };
+    let expr = parser.or()?;
+    let source = input[..parser.pos].trim().to_string();
+    let filters = match parser.eat("|") {
+        true => parser.filters()?,
+        false => Vec::new(),
+    };
+    parser.end()?;
+    Ok(Var {
+        source,
+        expr,
+        filters,
+    })
+}
+
+///Parses filters separated by `|`, like `truncate(20) | upper`.
+pub fn parse_filters(input: &str) -> Result<Vec<Filter>, String> {
+    let mut parser = Parser { input, pos: 0 };
+    let filters = parser.filters()?;
+    parser.end()?;
+    Ok(filters)
+}
+
+///Parses literals, paths, indexing, method calls, `!`, arithmetic,
+///comparisons, `&&`, `||` and parentheses.
+pub fn parse(input: &str) -> Result<Expr, String> {
+    let mut parser = Parser { input, pos: 0 };
+    let expr = parser.or()?;
+    parser.end()?;
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    ///Consumes the token if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            None => Ok(()),
+            Some(c) => Err(format!("unexpected `{}` at column {}", c, self.pos + 1)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(format!("expected a name at column {}", self.pos + 1));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn filters(&mut self) -> Result<Vec<Filter>, String> {
+        let mut filters = Vec::new();
+        loop {
+            let name = self.name()?;
+            let args = self
+                .args()?
+                .into_iter()
+                .map(|arg| match arg {
+                    Expr::Lit(value) => Ok(value),
+                    _ => Err(format!("the arguments of `{}` must be literals", name)),
+                })
+                .collect::<Result<_, _>>()?;
+            filters.push(Filter { name, args });
+            if !self.eat("|") {
+                return Ok(filters);
+            }
+        }
+    }
+
+    ///Parses `(a, b)`, if it comes next.
+    fn args(&mut self) -> Result<Vec<Expr>, String> {
+        let mut args = Vec::new();
+        if !self.eat("(") || self.eat(")") {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.or()?);
+            if self.eat(")") {
+                return Ok(args);
+            }
+            if !self.eat(",") {
+                return Err(format!("expected `,` or `)` at column {}", self.pos + 1));
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Binary(Box::new(expr), BinOp::Or, Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.comparison()?;
+        while self.eat("&&") {
+            expr = Expr::Binary(Box::new(expr), BinOp::And, Box::new(self.comparison()?));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        //Two character operators first, so `<=` isn't read as `<`
+        let ops = [
+            ("==", BinOp::Eq),
+            ("!=", BinOp::Ne),
+            ("<=", BinOp::Le),
+            (">=", BinOp::Ge),
+            ("<", BinOp::Lt),
+            (">", BinOp::Gt),
+        ];
+        for (token, op) in ops {
+            if self.eat(token) {
+                return Ok(Expr::Binary(Box::new(left), op, Box::new(self.sum()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        self.binary(&[("+", BinOp::Add), ("-", BinOp::Sub)], Parser::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let ops = [("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)];
+        self.binary(&ops, Parser::unary)
+    }
+
+    ///Parses left associative operators of the same precedence.
+    fn binary(
+        &mut self,
+        ops: &[(&str, BinOp)],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+        'outer: loop {
+            for (token, op) in ops {
+                if self.eat(token) {
+                    expr = Expr::Binary(Box::new(expr), *op, Box::new(operand(self)?));
+                    continue 'outer;
+                }
+            }
+            return Ok(expr);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    ///Parses indexing and method calls, like `map["key"]` or `items.len()`.
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat("[") {
+                let index = self.or()?;
+                if !self.eat("]") {
+                    return Err(format!("expected `]` at column {}", self.pos + 1));
+                }
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else if self.eat(".") {
+                let name = self.name()?;
+                expr = match self.rest().trim_start().starts_with('(') {
+                    true => Expr::Call(Box::new(expr), name, self.args()?),
+                    false => Expr::Index(Box::new(expr), Box::new(Expr::Lit(Value::Str(name)))),
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err(format!("expected `)` at column {}", self.pos + 1));
+            }
+            return Ok(expr);
+        }
+        self.skip_whitespace();
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some('"') => {
+                let len = rest[1..]
+                    .find('"')
+                    .ok_or_else(|| "unclosed string".to_string())?;
+                self.pos += len + 2;
+                Ok(Expr::Lit(Value::Str(rest[1..len + 1].to_string())))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' => {
+                let digits = |from: usize| {
+                    rest[from..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .map_or(rest.len(), |len| from + len)
+                };
+                let mut len = digits(1);
+                //A `.` without a digit after it is a method call, like `0.len()`
+                if rest[len..].starts_with('.')
+                    && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    len = digits(len + 1);
+                }
+                let number = &rest[..len];
+                self.pos += len;
+                match number.parse::<i64>() {
+                    Ok(int) => Ok(Expr::Lit(Value::Int(int))),
+                    Err(_) => number
+                        .parse::<f64>()
+                        .map(|float| Expr::Lit(Value::Float(float)))
+                        .map_err(|_| format!("invalid number `{}`", number)),
+                }
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let mut len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len());
+                //The last name before `(` is a method, like `len` in `items.len()`
+                if rest[len..].trim_start().starts_with('(') {
+                    len = rest[..len].rfind('.').unwrap_or(0);
+                }
+                let path = &rest[..len];
+                self.pos += len;
+                match path {
+                    "" => Err(format!("unknown function at column {}", self.pos + 1)),
+                    "true" => Ok(Expr::Lit(Value::Bool(true))),
+                    "false" => Ok(Expr::Lit(Value::Bool(false))),
+                    _ if !is_path(path) => Err(format!("invalid path `{}`", path)),
+                    _ => Ok(Expr::Path(path.to_string())),
+                }
+            }
+            Some(c) => Err(format!("unexpected `{}` at column {}", c, self.pos + 1)),
+            None => Err("unexpected end of the expression".to_string()),
+        }
+    }
+}
+
+///The first name of a path is an ident, the others can be list indices like `0`.
+fn is_path(path: &str) -> bool {
+    path.split('.').enumerate().all(|(i, name)| {
+        let digits = i > 0 && name.chars().all(|c| c.is_ascii_digit());
+        !name.is_empty()
+            && (digits || !name.starts_with(|c: char| c.is_ascii_digit()))
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> Box<Expr> {
+        Box::new(Expr::Path(name.to_string()))
+    }
+
+    #[test]
+    fn parses_precedence() {
+        let expr = parse("!a.b || count >= 2 && (c)").unwrap();
+        assert_eq!(
+            expr,
+            Expr::Binary(
+                Box::new(Expr::Not(path("a.b"))),
+                BinOp::Or,
+                Box::new(Expr::Binary(
+                    Box::new(Expr::Binary(
+                        path("count"),
+                        BinOp::Ge,
+                        Box::new(Expr::Lit(Value::Int(2)))
+                    )),
+                    BinOp::And,
+                    path("c")
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parses_arithmetic() {
+        let int = |i: i64| Box::new(Expr::Lit(Value::Int(i)));
+        assert_eq!(
+            parse("a - 1 + b * 2 % 3 > 0").unwrap(),
+            Expr::Binary(
+                Box::new(Expr::Binary(
+                    Box::new(Expr::Binary(path("a"), BinOp::Sub, int(1))),
+                    BinOp::Add,
+                    Box::new(Expr::Binary(
+                        Box::new(Expr::Binary(path("b"), BinOp::Mul, int(2))),
+                        BinOp::Rem,
+                        int(3)
+                    ))
+                )),
+                BinOp::Gt,
+                int(0)
+            )
+        );
+    }
+
+    #[test]
+    fn parses_indexing_and_methods() {
+        let key = |key: &str| Box::new(Expr::Lit(Value::Str(key.to_string())));
+        assert_eq!(
+            parse("user.tags.len()").unwrap(),
+            Expr::Call(path("user.tags"), "len".to_string(), vec![])
+        );
+        assert_eq!(
+            parse("map[\"a\"].b.contains(x)").unwrap(),
+            Expr::Call(
+                Box::new(Expr::Index(
+                    Box::new(Expr::Index(path("map"), key("a"))),
+                    key("b")
+                )),
+                "contains".to_string(),
+                vec![Expr::Path("x".to_string())]
+            )
+        );
+        assert_eq!(parse("pair.0").unwrap(), Expr::Path("pair.0".to_string()));
+        assert!(parse("len()").is_err());
+        assert!(parse("a.1b").is_err());
+        assert!(parse("a[0").is_err());
+    }
+
+    #[test]
+    fn parses_literals() {
+        assert_eq!(
+            parse("\"a b\"").unwrap(),
+            Expr::Lit(Value::Str("a b".into()))
+        );
+        assert_eq!(parse("-1.5").unwrap(), Expr::Lit(Value::Float(-1.5)));
+        assert_eq!(
+            parse("0.len()").unwrap(),
+            Expr::Call(
+                Box::new(Expr::Lit(Value::Int(0))),
+                "len".to_string(),
+                vec![]
+            )
+        );
+        assert_eq!(parse(" false ").unwrap(), Expr::Lit(Value::Bool(false)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert_eq!(parse("a b").unwrap_err(), "unexpected `b` at column 3");
+        assert_eq!(parse("(a").unwrap_err(), "expected `)` at column 3");
+        assert_eq!(
+            parse("a ==").unwrap_err(),
+            "unexpected end of the expression"
+        );
+        assert!(parse("a.").is_err());
+    }
+
+    #[test]
+    fn parses_filters() {
+        let var = parse_var(" name | truncate(20, \"…\") |upper| default() ").unwrap();
+        assert_eq!(var.source, "name");
+        assert_eq!(
+            var.filters,
+            vec![
+                Filter {
+                    name: "truncate".to_string(),
+                    args: vec![Value::Int(20), Value::Str("…".to_string())]
+                },
+                Filter {
+                    name: "upper".to_string(),
+                    args: vec![]
+                },
+                Filter {
+                    name: "default".to_string(),
+                    args: vec![]
+                }
+            ]
+        );
+        assert_eq!(parse_var("a.b").unwrap().filters, vec![]);
+        let var = parse_var("a || b | upper").unwrap();
+        assert_eq!(var.source, "a || b");
+        assert_eq!(var.filters.len(), 1);
+        assert!(parse_filters("").is_err());
+        assert!(parse_filters("upper |").is_err());
+        assert!(parse_filters("join(sep)").is_err());
+        assert!(parse_filters("truncate(1 2)").is_err());
+    }
+}
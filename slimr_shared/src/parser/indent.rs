@@ -0,0 +1,366 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::ast::{ParseError, ParseErrorKind};
+
+///The grammar only understands this indentation unit.
+///Every other unit is translated to it before parsing.
+pub(crate) const GRAMMAR_UNIT: &str = "  ";
+
+///The unit of indentation that opens a new level of nesting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Indent {
+    ///A fixed number of spaces per level.
+    Spaces(usize),
+    ///One tab per level.
+    Tabs,
+    ///Use whatever the first indented line of the template uses.
+    #[default]
+    Auto,
+}
+
+impl fmt::Display for Indent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Indent::Spaces(1) => write!(f, "1 space"),
+            Indent::Spaces(n) => write!(f, "{} spaces", n),
+            Indent::Tabs => write!(f, "tabs"),
+            Indent::Auto => write!(f, "auto-detected indentation"),
+        }
+    }
+}
+
+impl FromStr for Indent {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Indent::Auto),
+            "tab" | "tabs" => Ok(Indent::Tabs),
+            n => match n.parse() {
+                Ok(n) if n > 0 => Ok(Indent::Spaces(n)),
+                _ => Err(format!(
+                    "Unknown indentation {}, expected auto, tabs, or a number of spaces",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+///A template with its indentation translated to the grammar's unit.
+///`indents` holds the length of the original and the translated
+///indentation of every line in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Normalized {
+    pub text: String,
+    pub indents: Vec<(usize, usize)>,
+}
+
+///Checks that every line is indented with whole units of `indent`
+///and replaces each unit with the grammar's unit.
+///Lines that only contain whitespace or continue a block comment
+///are left as they are. The lines of a content block are text, so
+///only their leading whole units are replaced and the rest is kept.
+pub(crate) fn normalize(input: &str, indent: Indent) -> Result<Normalized, ParseError> {
+    let mut unit = indent;
+    let mut text = String::with_capacity(input.len());
+    let mut indents = Vec::new();
+    let mut in_comment = false;
+    //The indentation of the `tag:` line whose content block we're in
+    let mut block: Option<usize> = None;
+    let mut offset = 0;
+    for (nr, line) in input.split('\n').enumerate() {
+        if nr > 0 {
+            text.push('\n');
+        }
+        offset += line.len() + 1;
+        if in_comment {
+            in_comment = !line.contains("*/");
+            indents.push((0, 0));
+            text.push_str(line);
+            continue;
+        }
+        let ws_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+        let ws = &line[..ws_len];
+        let content = &line[ws_len..];
+        let mut in_block = false;
+        if !content.is_empty() {
+            //The lines of a content block are text, they don't have comments
+            block = block.filter(|&outer| ws_len > outer);
+            in_block = block.is_some();
+            if !in_block {
+                let following = input.get(offset..).unwrap_or_default();
+                in_comment = opens_block_comment(content, following);
+                if !in_comment && is_block_header(content) {
+                    block = Some(ws_len);
+                }
+            }
+        }
+        if ws.is_empty() || ws_len == line.len() {
+            indents.push((ws_len, ws_len));
+            text.push_str(line);
+            continue;
+        }
+        let error =
+            |msg: String| ParseError::new(nr + 1, 1, ParseErrorKind::Indentation(msg), line);
+        if in_block && unit != Indent::Auto {
+            let (levels, kept) = leading_units(ws, unit);
+            let translated = GRAMMAR_UNIT.repeat(levels) + kept;
+            indents.push((ws_len, translated.len()));
+            text.push_str(&translated);
+            text.push_str(content);
+            continue;
+        }
+        if ws.contains(' ') && ws.contains('\t') {
+            return Err(error("mixed tabs and spaces in indentation".to_string()));
+        }
+        if unit == Indent::Auto {
+            unit = detect(ws);
+        }
+        let levels = match unit {
+            Indent::Spaces(0) => {
+                return Err(error("indentation needs at least one space".to_string()))
+            }
+            Indent::Spaces(_) if ws.contains('\t') => {
+                return Err(error(format!("expected {}, found tabs", unit)))
+            }
+            Indent::Spaces(n) if ws_len % n != 0 => {
+                return Err(error(format!(
+                    "indentation of {} is not a multiple of {}",
+                    Indent::Spaces(ws_len),
+                    unit
+                )))
+            }
+            Indent::Spaces(n) => ws_len / n,
+            Indent::Tabs if ws.contains(' ') => {
+                return Err(error("expected tabs, found spaces".to_string()))
+            }
+            Indent::Tabs => ws_len,
+            Indent::Auto => unreachable!(),
+        };
+        let translated = GRAMMAR_UNIT.repeat(levels);
+        indents.push((ws_len, translated.len()));
+        text.push_str(&translated);
+        text.push_str(&line[ws_len..]);
+    }
+    Ok(Normalized { text, indents })
+}
+
+///Counts the whole units at the start of the indentation
+///and returns them with the rest of the indentation.
+fn leading_units(ws: &str, unit: Indent) -> (usize, &str) {
+    let (char, len) = match unit {
+        Indent::Spaces(n) => (' ', n.max(1)),
+        _ => ('\t', 1),
+    };
+    let units = (ws.len() - ws.trim_start_matches(char).len()) / len;
+    (units, &ws[units * len..])
+}
+
+///Checks whether a line starts a block comment that continues on the next
+///line. Like in the grammar, a comment starts at the beginning of the line or
+///after whitespace outside of quoted attribute values, and a block comment
+///needs to be closed, otherwise it's text.
+fn opens_block_comment(line: &str, following: &str) -> bool {
+    if let Some(comment) = line.strip_prefix("/*") {
+        return !comment.contains("*/") && following.contains("*/");
+    }
+    match comment_start(line) {
+        Some(pos) if line[pos..].starts_with("/*") => following.contains("*/"),
+        _ => false,
+    }
+}
+
+///Finds the start of a trailing comment that isn't closed on this line,
+///or of a line comment.
+fn comment_start(line: &str) -> Option<usize> {
+    if line.starts_with("//") || line.starts_with("/!") {
+        return Some(0);
+    }
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'=' if matches!(bytes.get(i + 1), Some(b'"' | b'\'')) => {
+                let quote = bytes[i + 1] as char;
+                let end = line[i + 2..].find(quote)?;
+                i += end + 3;
+                continue;
+            }
+            b' ' | b'\t' => {
+                let rest = &line[i + 1..];
+                if rest.starts_with("//") {
+                    return Some(i + 1);
+                }
+                if let Some(comment) = rest.strip_prefix("/*") {
+                    match comment.find("*/") {
+                        Some(end) => {
+                            i += end + 5;
+                            continue;
+                        }
+                        None => return Some(i + 1),
+                    }
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    None
+}
+
+///Whether the line is an element like `p.note title="a":` that's followed
+///by a block of content lines.
+fn is_block_header(line: &str) -> bool {
+    let line = match comment_start(line) {
+        Some(pos) => &line[..pos],
+        None => line,
+    };
+    let Some(head) = line.trim_end().strip_suffix(':') else {
+        return false;
+    };
+    if !head.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return false;
+    }
+    //After the tag there can only be attributes, anything else is content
+    let mut rest = head;
+    while let Some(pos) = rest.find([' ', '\t']) {
+        rest = rest[pos..].trim_start();
+        let Some(eq) = rest.find('=') else {
+            return false;
+        };
+        let name = &rest[..eq];
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return false;
+        }
+        rest = &rest[eq + 1..];
+        if let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            let Some(end) = rest[1..].find(quote) else {
+                return false;
+            };
+            rest = &rest[end + 2..];
+        }
+    }
+    true
+}
+
+fn detect(ws: &str) -> Indent {
+    if ws.starts_with('\t') {
+        Indent::Tabs
+    } else {
+        Indent::Spaces(ws.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_four_spaces() {
+        let res = normalize("div\n    p\n        span", Indent::Spaces(4)).unwrap();
+        assert_eq!(res.text, "div\n  p\n    span");
+        assert_eq!(res.indents, vec![(0, 0), (4, 2), (8, 4)]);
+    }
+
+    #[test]
+    fn translates_tabs() {
+        let res = normalize("div\n\tp\n\t\tspan", Indent::Tabs).unwrap();
+        assert_eq!(res.text, "div\n  p\n    span");
+    }
+
+    #[test]
+    fn detects_unit_from_first_indented_line() {
+        let res = normalize("div\n   p\n      span\nbr", Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  p\n    span\nbr");
+        let res = normalize("div\n\tp", Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  p");
+    }
+
+    #[test]
+    fn rejects_mixed_indentation() {
+        let err = normalize("div\n  p\n\tspan", Indent::Auto).unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Indentation("expected 2 spaces, found tabs".to_string())
+        );
+        let err = normalize("div\n \tp", Indent::Auto).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Indentation("mixed tabs and spaces in indentation".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_partial_units() {
+        let err = normalize("div\n    p\n      span", Indent::Auto).unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.snippet, "      span");
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::Indentation(
+                "indentation of 6 spaces is not a multiple of 4 spaces".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_partial_units_in_content_blocks() {
+        let res = normalize("div\n    p:\n        line\n          more", Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  p:\n    line\n      more");
+        let res = normalize("div\n\tp:\n\t\tline\n\t\t  more", Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  p:\n    line\n      more");
+    }
+
+    #[test]
+    fn keeps_block_comment_lines() {
+        let input = "div /* a comment\n       * over lines */\n  p";
+        let res = normalize(input, Indent::Auto).unwrap();
+        assert_eq!(res.text, input);
+        let res = normalize("/* one line */\ndiv\n    p", Indent::Auto).unwrap();
+        assert_eq!(res.text, "/* one line */\ndiv\n  p");
+    }
+
+    #[test]
+    fn ignores_comment_markers_in_text() {
+        assert!(!opens_block_comment("a href=\"/*\" x", "*/"));
+        assert!(!opens_block_comment("// no /* block", "*/"));
+        assert!(!opens_block_comment("p a/*b", "*/"));
+        assert!(opens_block_comment("p text /* block", "*/"));
+        assert!(opens_block_comment("/* block", " */"));
+        assert!(!opens_block_comment("p text /* never closed", "span"));
+        assert_eq!(comment_start("a href=\"http://x\" // c"), Some(18));
+        assert_eq!(comment_start("p see http://x.org"), None);
+    }
+
+    #[test]
+    fn indents_lines_after_comment_markers_in_text() {
+        let res = normalize("div\n    p a /* b\n        span\n    i", Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  p a /* b\n    span\n  i");
+        let input = "div\n    a href=\"http://x\" // c\n        span";
+        let res = normalize(input, Indent::Auto).unwrap();
+        assert_eq!(res.text, "div\n  a href=\"http://x\" // c\n    span");
+        let input = "style:\n    a { } /* x\n    b { } */\np\n    i";
+        let res = normalize(input, Indent::Auto).unwrap();
+        assert_eq!(res.text, "style:\n  a { } /* x\n  b { } */\np\n  i");
+    }
+
+    #[test]
+    fn finds_block_headers() {
+        assert!(is_block_header("p:"));
+        assert!(is_block_header("p.note#x title=\"a b:\" lang=en: // c"));
+        assert!(!is_block_header("p Note:"));
+        assert!(!is_block_header("- if a:"));
+    }
+
+    #[test]
+    fn parses_indent_names() {
+        assert_eq!("auto".parse(), Ok(Indent::Auto));
+        assert_eq!("Tabs".parse(), Ok(Indent::Tabs));
+        assert_eq!("4".parse(), Ok(Indent::Spaces(4)));
+        assert!("0".parse::<Indent>().is_err());
+        assert!("wide".parse::<Indent>().is_err());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod ast;
 pub mod ast_types;
+pub mod expr;
+pub mod indent;
+mod source_map;
+pub mod text;
@@ -0,0 +1,100 @@
+use super::ast_types::Span;
+use super::indent::Normalized;
+
+///Translates byte offsets of the parsed input into spans
+///with line and column numbers of the original template.
+///
+///The parsed input can differ from the original template in the
+///indentation of its lines, see `indent::normalize`.
+#[derive(Debug, Clone)]
+pub(crate) struct SourceMap<'a> {
+    input: &'a str,
+    line_starts: Vec<usize>,
+    parsed_line_starts: Vec<usize>,
+    indents: Vec<(usize, usize)>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(input: &'a str, parsed: &Normalized) -> SourceMap<'a> {
+        SourceMap {
+            input,
+            line_starts: line_starts(input),
+            parsed_line_starts: line_starts(&parsed.text),
+            indents: parsed.indents.clone(),
+        }
+    }
+
+    ///Returns the line index and the original offset of an offset in the parsed input.
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = match self.parsed_line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let in_line = offset - self.parsed_line_starts[line];
+        let (orig_indent, parsed_indent) = self.indents.get(line).copied().unwrap_or((0, 0));
+        let orig_in_line = if in_line >= parsed_indent {
+            in_line - parsed_indent + orig_indent
+        } else {
+            in_line * orig_indent / parsed_indent
+        };
+        (line, self.line_starts[line] + orig_in_line)
+    }
+
+    ///Returns the line and column of an offset in the parsed input, both starting at 1.
+    ///The column counts characters of the original line, not bytes.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let (line, orig) = self.locate(offset);
+        let col = self.input[self.line_starts[line]..orig].chars().count() + 1;
+        (line + 1, col)
+    }
+
+    ///Returns the original text of a line, starting at 1.
+    pub fn line(&self, line: usize) -> &'a str {
+        self.input.lines().nth(line - 1).unwrap_or("")
+    }
+
+    pub fn span(&self, span: pest::Span) -> Span {
+        let (line, col) = self.line_col(span.start());
+        Span {
+            start: self.locate(span.start()).1,
+            end: self.locate(span.end()).1,
+            line,
+            col,
+        }
+    }
+}
+
+fn line_starts(text: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::indent::{normalize, Indent};
+
+    #[test]
+    fn finds_line_and_column() {
+        let input = "div\n  p hällo\nspan";
+        let parsed = normalize(input, Indent::Auto).unwrap();
+        let map = SourceMap::new(input, &parsed);
+        assert_eq!(map.line_col(0), (1, 1));
+        assert_eq!(map.line_col(4), (2, 1));
+        assert_eq!(map.line_col(6), (2, 3));
+        assert_eq!(map.line_col(15), (3, 1));
+        assert_eq!(map.line_col(13), (2, 9));
+    }
+
+    #[test]
+    fn maps_translated_indentation_back() {
+        let input = "div\n\t\tp hello";
+        let parsed = normalize(input, Indent::Tabs).unwrap();
+        let map = SourceMap::new(input, &parsed);
+        //"p" is at offset 8 in the parsed input
+        assert_eq!(map.line_col(8), (2, 3));
+        assert_eq!(map.locate(8), (1, 6));
+        assert_eq!(map.line(2), "\t\tp hello");
+    }
+}
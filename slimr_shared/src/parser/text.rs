@@ -0,0 +1,194 @@
+///A piece of content text or of an attribute value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    ///`{pv{name}}`, a variable that's assigned by the child template
+    ParentVar(&'a str),
+    ///`{{ name }}`, a value that's escaped when it's inserted.
+    ///The name is an expression like `user.name` or `items.len()`,
+    ///followed by filters like `user.name | upper`.
+    Var(&'a str),
+    ///`{{{ name }}}`, trusted html that's inserted as it is
+    RawVar(&'a str),
+}
+
+const PV_START: &str = "{pv{";
+const RAW_START: &str = "{{{";
+const RAW_END: &str = "}}}";
+const VAR_START: &str = "{{";
+const VAR_END: &str = "}}";
+
+///Splits text into plain text and the variables inside it. Everything
+///between `{{` and `}}` is a variable, so that the renderer and the derive
+///can report the ones that aren't valid expressions.
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut res = Vec::new();
+    let mut rest = text;
+    let mut plain_len = 0;
+    while let Some(pos) = rest[plain_len..].find('{') {
+        let start = plain_len + pos;
+        match variable(&rest[start..]) {
+            Some((segment, len)) => {
+                if start > 0 {
+                    res.push(Segment::Text(&rest[..start]));
+                }
+                res.push(segment);
+                rest = &rest[start + len..];
+                plain_len = 0;
+            }
+            None => plain_len = start + 1,
+        }
+    }
+    if !rest.is_empty() {
+        res.push(Segment::Text(rest));
+    }
+    res
+}
+
+///Reads the variable at the start of the text and returns it with its length.
+fn variable(text: &str) -> Option<(Segment<'_>, usize)> {
+    let enclosed = |start: &str, end: &str| {
+        let inner = text.strip_prefix(start)?;
+        let len = inner.find(end)?;
+        Some((&inner[..len], start.len() + len + end.len()))
+    };
+    if let Some((name, len)) = enclosed(PV_START, VAR_END) {
+        return is_var_name(name).then_some((Segment::ParentVar(name), len));
+    }
+    if let Some((name, len)) = enclosed(RAW_START, RAW_END) {
+        if is_var(name) {
+            return Some((Segment::RawVar(name.trim()), len));
+        }
+    }
+    let (name, len) = enclosed(VAR_START, VAR_END)?;
+    is_var(name).then_some((Segment::Var(name.trim()), len))
+}
+
+///Returns the names of all parent variables used in the text.
+pub fn parent_vars(text: &str) -> impl Iterator<Item = &str> {
+    segments(text).into_iter().filter_map(|s| match s {
+        Segment::ParentVar(name) => Some(name),
+        _ => None,
+    })
+}
+
+///Parent variable names follow the same rules as in `<pv name`.
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' => {
+            chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        }
+        _ => false,
+    }
+}
+
+///A variable is anything but blanks that doesn't start another variable,
+///so that `{{{a}}` is a brace followed by the variable `a`.
+fn is_var(var: &str) -> bool {
+    let var = var.trim();
+    !var.is_empty() && !var.starts_with('{') && !var.starts_with("pv{")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment::*;
+    use super::*;
+
+    #[test]
+    fn keeps_plain_text() {
+        assert_eq!(segments("hello world"), vec![Text("hello world")]);
+        assert_eq!(segments(""), vec![]);
+    }
+
+    #[test]
+    fn finds_parent_vars() {
+        assert_eq!(
+            segments("{pv{title}} - {pv{site_name}}!"),
+            vec![
+                ParentVar("title"),
+                Text(" - "),
+                ParentVar("site_name"),
+                Text("!")
+            ]
+        );
+    }
+
+    #[test]
+    fn ignores_invalid_parent_vars() {
+        assert_eq!(
+            segments("{pv{no way}} {pv{open"),
+            vec![Text("{pv{no way}} {pv{open")]
+        );
+        assert_eq!(segments("{pv{{pv{a}}"), vec![Text("{pv{"), ParentVar("a")]);
+    }
+
+    #[test]
+    fn finds_vars_by_their_delimiters() {
+        assert_eq!(
+            segments("{{ a as u32 }} {{ name.len( }}{{{ f(x) }}}"),
+            vec![Var("a as u32"), Text(" "), Var("name.len("), RawVar("f(x)")]
+        );
+        assert_eq!(segments("{{ }} {}"), vec![Text("{{ }} {}")]);
+    }
+
+    #[test]
+    fn finds_vars() {
+        assert_eq!(
+            segments("Hello {{name}}, {{ greeting }}!"),
+            vec![
+                Text("Hello "),
+                Var("name"),
+                Text(", "),
+                Var("greeting"),
+                Text("!")
+            ]
+        );
+        assert_eq!(
+            segments("{{{ html }}}{{{x}}}"),
+            vec![RawVar("html"), RawVar("x")]
+        );
+    }
+
+    #[test]
+    fn finds_dotted_vars() {
+        assert_eq!(
+            segments("{{ user.address.city }}, {{{posts.0}}}"),
+            vec![Var("user.address.city"), Text(", "), RawVar("posts.0")]
+        );
+    }
+
+    #[test]
+    fn finds_vars_with_filters() {
+        assert_eq!(
+            segments("{{ name | truncate(20) | upper }}{{{ body|trim }}}"),
+            vec![Var("name | truncate(20) | upper"), RawVar("body|trim")]
+        );
+        assert_eq!(segments("{{a || b}}"), vec![Var("a || b")]);
+    }
+
+    #[test]
+    fn finds_expression_vars() {
+        assert_eq!(
+            segments("{{ items.len() }} {{map[\"key\"]}}{{{ a * (b + 1) }}}"),
+            vec![
+                Var("items.len()"),
+                Text(" "),
+                Var("map[\"key\"]"),
+                RawVar("a * (b + 1)")
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_other_braces() {
+        assert_eq!(segments("{"), vec![Text("{")]);
+        assert_eq!(segments("}}"), vec![Text("}}")]);
+        assert_eq!(segments("{}"), vec![Text("{}")]);
+        assert_eq!(segments("{{{a}}"), vec![Text("{"), Var("a")]);
+        assert_eq!(
+            segments("{{{{a}}}}"),
+            vec![Text("{"), RawVar("a"), Text("}")]
+        );
+    }
+}
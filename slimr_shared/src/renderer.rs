@@ -1,108 +1,863 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{fmt, io};
+
 use template_manager::TemplateManager;
+use thiserror::Error;
 
+use crate::escape::Escape;
+use crate::output::{Arm, Branch, Output, Part};
 use crate::parser;
-use crate::parser::ast_types::{Cont, Node};
-use crate::template_manager;
+use crate::parser::ast::{ParseError, ParseErrorKind, ParseOptions};
+use crate::parser::ast_types::{Comment, Cont, Elem, For, If, Include, Match, Node, Parent, Span};
+use crate::parser::expr;
+use crate::parser::indent::Indent;
+use crate::parser::text::{self, Segment};
+use crate::template_manager::{self, TemplateManagerError};
+use crate::write::IoWriter;
 
-pub fn render_source(source: &str) -> String {
-    let input = parser::ast::from_str(source);
-    render_ast(input)
+///Options that change how the html is rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    ///Put nested elements on their own lines and indent them.
+    ///Content stays on the line of its element.
+    pub pretty: bool,
+    ///The indentation of a nesting level in pretty mode.
+    pub indent: String,
+    pub dialect: Dialect,
+    ///The indentation unit of the template source. See [`ParseOptions`].
+    pub parse_indent: Indent,
 }
 
-pub fn render_path(path: &str) -> String {
-    let mut tm = TemplateManager::default();
-    let source = tm
-        .get(path)
-        .expect(&format!("Template Manager couldn't find path: {}", path));
-    render_source(&source)
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            pretty: false,
+            indent: "  ".to_string(),
+            dialect: Dialect::default(),
+            parse_indent: Indent::default(),
+        }
+    }
 }
 
-fn render_ast(ast: Vec<Node>) -> String {
-    let mut result = String::new();
-    for node in ast {
-        result.push_str(&render_node(&node));
+///The flavour of markup that is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    ///Void elements are written as `<br>`
+    #[default]
+    Html5,
+    ///Void elements are written as `<br />`
+    Xhtml,
+    ///There are no void elements. Every empty element is written as `<tag/>`
+    Xml,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "html" | "html5" => Ok(Dialect::Html5),
+            "xhtml" => Ok(Dialect::Xhtml),
+            "xml" => Ok(Dialect::Xml),
+            _ => Err(format!(
+                "Unknown dialect {}, expected html5, xhtml, or xml",
+                s
+            )),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn pretty() -> Self {
+        RenderOptions {
+            pretty: true,
+            ..RenderOptions::default()
+        }
     }
-    result
 }
 
-fn render_node(node: &Node) -> String {
-    let mut result = String::new();
-    let mut opening = String::new();
-    let mut content = String::new();
-    let elem = match node {
-        Node::ELEM(el) => el,
-        _ => unimplemented!(),
-    };
+///Elements that never have content or a closing tag.
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+///Elements whose whitespace is part of their content.
+///They are never pretty-printed.
+const WHITESPACE_SENSITIVE: [&str; 2] = ["pre", "textarea"];
 
-    opening.push_str(&elem.tag);
+///Elements whose content is not html, so it is never escaped.
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+///Renders the template. Its variables are written
+///as they are, like `{{name}}`.
+pub fn render_source(source: &str, options: &RenderOptions) -> Result<String> {
+    compile_source(source, options).map(|output| output.to_string())
+}
 
-    if let Some(id) = &elem.id {
-        opening.push_str(&format!(r#" id="{}""#, &id));
+pub fn render_path(path: &str, options: &RenderOptions) -> Result<String> {
+    compile_path(path, options).map(|output| output.to_string())
+}
+
+///Renders the template at `path` and resolves its includes
+///through the given template manager.
+pub fn render_template(
+    tm: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<String> {
+    compile_template(tm, path, options).map(|output| output.to_string())
+}
+
+///Renders the template at `path` into `w`. The html is built in memory
+///first and only written when the whole template rendered, so an error
+///leaves nothing written.
+pub fn render_into(
+    tm: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+    w: &mut impl fmt::Write,
+) -> Result<()> {
+    let output = compile_template(tm, path, options)?;
+    write!(w, "{}", output)?;
+    Ok(())
+}
+
+///Renders the template at `path` into a file, a socket or any other `io::Write`.
+///Like `render_into`, the html is built in memory first.
+pub fn render_into_io(
+    tm: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+    w: &mut impl io::Write,
+) -> Result<()> {
+    let output = compile_template(tm, path, options)?;
+    let mut writer = IoWriter::new(w);
+    let result = fmt::Write::write_fmt(&mut writer, format_args!("{}", output));
+    writer
+        .finish(result)
+        .map_err(|e| RenderError::Io(IoError(Arc::new(e))))
+}
+
+///Renders the template but keeps its variables apart from the html,
+///so they can be filled in and escaped later.
+pub fn compile_source(source: &str, options: &RenderOptions) -> Result<Output> {
+    let mut tm = TemplateManager::default();
+    let mut renderer = Renderer::new(&mut tm, options);
+    renderer.render_source(source)?;
+    Ok(renderer.out)
+}
+
+pub fn compile_path(path: &str, options: &RenderOptions) -> Result<Output> {
+    let mut tm = TemplateManager::default();
+    compile_template(&mut tm, path, options)
+}
+
+///Like `compile_source`, but keeps the expressions as they are
+///written, because derived templates parse them as Rust.
+pub fn compile_rust_source(source: &str, options: &RenderOptions) -> Result<Output> {
+    let mut tm = TemplateManager::default();
+    let mut renderer = Renderer::new(&mut tm, options);
+    renderer.runtime = false;
+    renderer.render_source(source)?;
+    Ok(renderer.out)
+}
+
+///Like `compile_path`, but keeps the expressions as they are written.
+pub fn compile_rust_path(path: &str, options: &RenderOptions) -> Result<Output> {
+    let mut tm = TemplateManager::default();
+    let mut renderer = Renderer::new(&mut tm, options);
+    renderer.runtime = false;
+    renderer.render_path(path)?;
+    Ok(renderer.out)
+}
+
+pub fn compile_template(
+    tm: &mut TemplateManager,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<Output> {
+    let mut renderer = Renderer::new(tm, options);
+    renderer.render_path(path)?;
+    Ok(renderer.out)
+}
+
+///Renders an AST into html.
+///Everything is written into one output buffer, except for the
+///body of a child template, which is rendered before its parent.
+///It keeps track of the templates that are currently being
+///rendered to detect cycles.
+struct Renderer<'a> {
+    tm: &'a mut TemplateManager,
+    options: &'a RenderOptions,
+    out: Output,
+    ///False inside whitespace sensitive elements, even in pretty mode
+    pretty: bool,
+    ///The nesting level in pretty mode
+    depth: usize,
+    ///Whether html comes before `out`, when `out` is the body of a branch
+    preceded: bool,
+    stack: Vec<String>,
+    layout: Layout,
+    ///Whether expressions are parsed for the interpreter. Derived
+    ///templates parse them as Rust instead.
+    runtime: bool,
+    ///The source of the current template, for the snippets of errors
+    source: String,
+}
+
+///What a child template passes to its parent template.
+#[derive(Debug, Default)]
+struct Layout {
+    vars: BTreeMap<String, String>,
+    child: Option<Output>,
+}
+
+impl<'a> Renderer<'a> {
+    fn new(tm: &'a mut TemplateManager, options: &'a RenderOptions) -> Renderer<'a> {
+        Renderer {
+            tm,
+            options,
+            out: Output::default(),
+            pretty: options.pretty,
+            depth: 0,
+            preceded: false,
+            stack: vec![],
+            layout: Layout::default(),
+            runtime: true,
+            source: String::new(),
+        }
+    }
+
+    fn render_source(&mut self, source: &str) -> Result<()> {
+        let parse_options = ParseOptions {
+            indent: self.options.parse_indent,
+        };
+        let input = parser::ast::from_str_with(source, &parse_options)?;
+        self.check_parent_vars(&input)?;
+        self.out.reserve(size_hint(&input));
+        let outer = std::mem::replace(&mut self.source, source.to_string());
+        let result = self.render_template(&input);
+        self.source = outer;
+        result
+    }
+
+    ///Renders a whole template. If it declares a parent, the rendered
+    ///template is inserted into the parent at `=>child`.
+    fn render_template(&mut self, ast: &[Node]) -> Result<()> {
+        let parent = ast.iter().find_map(|node| match node {
+            Node::PARENT(parent) => Some(parent),
+            _ => None,
+        });
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return self.render_ast(ast),
+        };
+        let outer = std::mem::take(&mut self.out);
+        let depth = std::mem::take(&mut self.depth);
+        let preceded = std::mem::take(&mut self.preceded);
+        let result = self.render_ast(ast);
+        let body = std::mem::replace(&mut self.out, outer);
+        self.depth = depth;
+        self.preceded = preceded;
+        result?;
+        let mut vars = BTreeMap::new();
+        for node in ast {
+            if let Node::PV(var) = node {
+                vars.insert(var.name.clone(), self.fill_parent_vars(&var.value.text)?);
+            }
+        }
+        let layout = Layout {
+            vars,
+            child: Some(body),
+        };
+        let outer = std::mem::replace(&mut self.layout, layout);
+        let result = self.render_parent(parent);
+        self.layout = outer;
+        result
+    }
+
+    fn render_parent(&mut self, parent: &Parent) -> Result<()> {
+        let result = match self.render_path(&parent.path) {
+            Ok(()) if self.layout.child.is_some() => Err(RenderError::MissingChild),
+            result => result,
+        };
+        result.map_err(|e| match e {
+            RenderError::Cycle(_) => e,
+            _ => RenderError::Parent {
+                path: parent.path.clone(),
+                line: parent.span.line,
+                col: parent.span.col,
+                source: Box::new(e),
+            },
+        })
     }
 
-    if let Some(classes) = &elem.classes {
-        opening.push_str(&format!(r#" class="{}""#, classes.join(" ")));
+    ///Checks that the child template assigns exactly the
+    ///parent variables that are used in the template.
+    fn check_parent_vars(&self, ast: &[Node]) -> Result<()> {
+        let mut used = BTreeSet::new();
+        collect_parent_vars(ast, &mut used);
+        if let Some(name) = used.iter().find(|n| !self.layout.vars.contains_key(**n)) {
+            return Err(RenderError::MissingParentVar(name.to_string()));
+        }
+        if self.layout.child.is_some() {
+            if let Some(name) = self.layout.vars.keys().find(|n| !used.contains(n.as_str())) {
+                return Err(RenderError::UnknownParentVar(name.to_string()));
+            }
+        }
+        Ok(())
     }
 
-    if let Some(attrs) = &elem.attr {
-        for a in attrs {
-            opening.push_str(&format!(r#" {}"#, a));
+    ///Replaces the parent variables in the text with their values.
+    fn fill_parent_vars(&self, text: &str) -> Result<String> {
+        let mut res = String::with_capacity(text.len());
+        for segment in text::segments(text) {
+            match segment {
+                Segment::ParentVar(name) => match self.layout.vars.get(name) {
+                    Some(value) => res.push_str(value),
+                    None => return Err(RenderError::MissingParentVar(name.to_string())),
+                },
+                other => res.push_str(&Part::from(other).to_string()),
+            }
         }
+        Ok(res)
     }
 
-    if let Some(c) = &elem.cont {
-        content.push_str(&render_elem_content(c));
+    fn render_path(&mut self, path: &str) -> Result<()> {
+        if let Some(pos) = self.stack.iter().position(|p| p == path) {
+            let mut cycle = self.stack[pos..].to_vec();
+            cycle.push(path.to_string());
+            return Err(RenderError::Cycle(cycle));
+        }
+        let source = self.tm.get(path).map_err(|e| RenderError::Template {
+            path: path.to_string(),
+            source: e,
+        })?;
+        self.stack.push(path.to_string());
+        let result = self.render_source(&source);
+        self.stack.pop();
+        result
     }
 
-    if let Some(children) = &elem.children {
-        for child in children {
-            content.push_str(&render_node(child));
+    fn render_ast(&mut self, ast: &[Node]) -> Result<()> {
+        for node in ast {
+            self.render_node(node)?;
         }
+        Ok(())
     }
 
-    if content == "" {
-        result.push_str(&format!("<{} />", opening))
-    } else {
-        result.push_str(&format!(
-            "<{op}>{co}</{cl}>",
-            op = opening,
-            co = content,
-            cl = elem.tag
-        ))
+    fn render_node(&mut self, node: &Node) -> Result<()> {
+        match node {
+            Node::ELEM(el) => self.render_elem(el),
+            Node::COMMENT(comment) => self.render_comment(comment),
+            Node::INCLUDE(include) => self.render_include(include),
+            Node::CHILD(_) => {
+                if let Some(child) = self.layout.child.take() {
+                    self.render_child(child);
+                }
+                Ok(())
+            }
+            Node::IF(node) => self.render_if(node),
+            Node::FOR(node) => self.render_for(node),
+            Node::MATCH(node) => self.render_match(node),
+            Node::PARENT(_) | Node::PV(_) => Ok(()),
+        }
     }
 
-    result
+    ///Renders every branch into its own output. Which one is
+    ///used is decided when the variables are filled in.
+    fn render_if(&mut self, node: &If) -> Result<()> {
+        let mut branches = Vec::with_capacity(node.branches.len());
+        for branch in &node.branches {
+            let parsed = self.parse_expr(branch.span, &branch.cond, || {
+                if branch.cond.starts_with("let ") {
+                    return Err("`- if let` is only supported in derived templates".to_string());
+                }
+                expr::parse(&branch.cond)
+            })?;
+            branches.push(Branch {
+                cond: branch.cond.clone(),
+                parsed,
+                body: self.render_body(&branch.children)?,
+            });
+        }
+        let otherwise = match &node.otherwise {
+            Some(children) => Some(self.render_body(children)?),
+            None => None,
+        };
+        self.out.push(Part::If {
+            branches,
+            otherwise,
+        });
+        Ok(())
+    }
+
+    fn render_for(&mut self, node: &For) -> Result<()> {
+        let source = format!("{} in {}", node.pattern, node.iter);
+        let parsed = self.parse_expr(node.span, &source, || {
+            let mut chars = node.pattern.chars();
+            let is_name = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_name {
+                return Err("only a single name can be bound".to_string());
+            }
+            expr::parse(&node.iter)
+        })?;
+        let body = self.render_body(&node.children)?;
+        let empty = match &node.empty {
+            Some(children) => Some(self.render_body(children)?),
+            None => None,
+        };
+        self.out.push(Part::For {
+            pattern: node.pattern.clone(),
+            iter: node.iter.clone(),
+            parsed,
+            body,
+            empty,
+        });
+        Ok(())
+    }
+
+    fn render_match(&mut self, node: &Match) -> Result<()> {
+        self.parse_expr(node.span, &node.expr, || {
+            Err::<(), _>("`- match` is only supported in derived templates".to_string())
+        })?;
+        let mut arms = Vec::with_capacity(node.arms.len());
+        for arm in &node.arms {
+            arms.push(Arm {
+                pattern: arm.pattern.clone(),
+                body: self.render_body(&arm.children)?,
+            });
+        }
+        self.out.push(Part::Match {
+            expr: node.expr.clone(),
+            arms,
+        });
+        Ok(())
+    }
+
+    ///Splits text into plain text and variables. The variables of
+    ///runtime templates are parsed here, so their errors point to the
+    ///template.
+    fn segments<'t>(&self, span: Span, text: &'t str) -> Result<Vec<Segment<'t>>> {
+        let segments = text::segments(text);
+        for segment in &segments {
+            if let Segment::Var(var) | Segment::RawVar(var) = segment {
+                self.parse_expr(span, var, || expr::parse_var(var))?;
+            }
+        }
+        Ok(segments)
+    }
+
+    ///Parses an expression of a runtime template, so that it's only
+    ///parsed once and its errors point to the template.
+    fn parse_expr<T>(
+        &self,
+        span: Span,
+        source: &str,
+        parse: impl FnOnce() -> Result<T, String>,
+    ) -> Result<Option<T>> {
+        if !self.runtime {
+            return Ok(None);
+        }
+        parse().map(Some).map_err(|message| {
+            let snippet = self.source.lines().nth(span.line - 1).unwrap_or_default();
+            let kind =
+                ParseErrorKind::Other(format!("invalid expression `{}`: {}", source, message));
+            ParseError::new(span.line, span.col, kind, snippet).into()
+        })
+    }
+
+    fn render_body(&mut self, ast: &[Node]) -> Result<Output> {
+        let preceded = self.preceded;
+        self.preceded |= !self.out.is_empty();
+        let outer = std::mem::take(&mut self.out);
+        let result = self.render_ast(ast);
+        self.preceded = preceded;
+        let body = std::mem::replace(&mut self.out, outer);
+        result.map(|_| body)
+    }
+
+    ///In pretty mode, every node starts on a new line
+    ///that's indented by its nesting level.
+    fn start_line(&mut self) {
+        if !self.pretty {
+            return;
+        }
+        if self.preceded || !self.out.is_empty() {
+            self.out.push_str("\n");
+        }
+        for _ in 0..self.depth {
+            self.out.push_str(&self.options.indent);
+        }
+    }
+
+    ///Renders the included template in place of the include node.
+    ///Errors inside the included template are wrapped with the
+    ///position of the include.
+    fn render_include(&mut self, include: &Include) -> Result<()> {
+        let outer = std::mem::take(&mut self.layout);
+        let result = self.render_path(&include.path);
+        self.layout = outer;
+        result.map_err(|e| match e {
+            RenderError::Cycle(_) => e,
+            _ => RenderError::Include {
+                path: include.path.clone(),
+                line: include.span.line,
+                col: include.span.col,
+                source: Box::new(e),
+            },
+        })
+    }
+
+    ///Inserts the body of the child template. It was rendered
+    ///without indentation, so in pretty mode its lines are indented here.
+    fn render_child(&mut self, child: Output) {
+        if child.is_empty() {
+            return;
+        }
+        if !self.pretty || self.depth == 0 {
+            self.start_line();
+            self.out.append(child);
+            return;
+        }
+        self.start_line();
+        let newline = format!("\n{}", self.options.indent.repeat(self.depth));
+        self.out
+            .append(child.map_static(&|html| html.replace('\n', &newline)));
+    }
+
+    fn render_elem(&mut self, elem: &Elem) -> Result<()> {
+        //HTML tag names are case insensitive
+        let void = self.options.dialect != Dialect::Xml
+            && VOID_ELEMENTS
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(&elem.tag));
+        if void && (elem.cont.is_some() || elem.children.is_some()) {
+            return Err(RenderError::VoidContent {
+                tag: elem.tag.clone(),
+                line: elem.span.line,
+                col: elem.span.col,
+            });
+        }
+        self.start_line();
+        self.out.push_str("<");
+        self.out.push_str(&elem.tag);
+
+        if let Some(id) = &elem.id {
+            self.out.push_str(r#" id=""#);
+            self.out
+                .push_text(self.segments(elem.span, id)?, Escape::Attr);
+            self.out.push_str("\"");
+        }
+
+        //The classes of the shorthand and of all class attributes are merged
+        //into one attribute, which is written at the first of them
+        let mut classes_written = elem.classes.is_some();
+        if classes_written {
+            self.render_classes(elem)?;
+        }
+        for a in elem.attr.iter().flatten() {
+            if a.name == "class" {
+                if !classes_written {
+                    self.render_classes(elem)?;
+                    classes_written = true;
+                }
+                continue;
+            }
+            self.out.push_str(" ");
+            self.out.push_str(&a.name);
+            self.out.push_str(r#"=""#);
+            let value = self.fill_parent_vars(&a.value)?;
+            self.out
+                .push_text(self.segments(a.span, &value)?, Escape::Attr);
+            self.out.push_str("\"");
+        }
+
+        if void {
+            match self.options.dialect {
+                Dialect::Html5 => self.out.push_str(">"),
+                _ => self.out.push_str(" />"),
+            }
+            return Ok(());
+        }
+        self.out.push_str(">");
+        let start = self.out.len();
+
+        //Content and children are only put on their own lines if there are children
+        let outer = self.pretty;
+        let sensitive = WHITESPACE_SENSITIVE
+            .iter()
+            .any(|t| match self.options.dialect {
+                Dialect::Xml => *t == elem.tag,
+                _ => t.eq_ignore_ascii_case(&elem.tag),
+            });
+        self.pretty &= elem.children.is_some() && !sensitive;
+        self.depth += 1;
+        let result = self.render_elem_content(elem);
+        self.depth -= 1;
+        let nested = std::mem::replace(&mut self.pretty, outer);
+        result?;
+
+        if self.out.len() == start && self.options.dialect == Dialect::Xml {
+            self.out.pop_str(">");
+            self.out.push_str("/>");
+            return Ok(());
+        }
+        if nested {
+            self.start_line();
+        }
+        self.out.push_str("</");
+        self.out.push_str(&elem.tag);
+        self.out.push_str(">");
+        Ok(())
+    }
+
+    fn render_classes(&mut self, elem: &Elem) -> Result<()> {
+        self.out.push_str(r#" class=""#);
+        let mut first = true;
+        for class in elem.classes.iter().flatten() {
+            if !std::mem::take(&mut first) {
+                self.out.push_str(" ");
+            }
+            self.out
+                .push_text(self.segments(elem.span, class)?, Escape::Attr);
+        }
+        for a in elem.attr.iter().flatten().filter(|a| a.name == "class") {
+            if !std::mem::take(&mut first) {
+                self.out.push_str(" ");
+            }
+            let value = self.fill_parent_vars(&a.value)?;
+            self.out
+                .push_text(self.segments(a.span, &value)?, Escape::Attr);
+        }
+        self.out.push_str("\"");
+        Ok(())
+    }
+
+    fn render_elem_content(&mut self, elem: &Elem) -> Result<()> {
+        let raw = self.options.dialect != Dialect::Xml
+            && RAW_TEXT.iter().any(|t| t.eq_ignore_ascii_case(&elem.tag));
+        let line_break = match self.options.dialect {
+            _ if raw => "\n",
+            Dialect::Html5 => "<br>",
+            Dialect::Xhtml => "<br />",
+            //XML has no line break element
+            Dialect::Xml => "\n",
+        };
+        let lines = match &elem.cont {
+            Some(Cont::LINE(l)) => std::slice::from_ref(l),
+            Some(Cont::BLOCK(b)) => b.as_slice(),
+            None => &[],
+        };
+        if !lines.is_empty() {
+            self.start_line();
+        }
+        for (i, l) in lines.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(line_break);
+            }
+            let text = self.fill_parent_vars(&l.text)?;
+            if raw {
+                self.out.push_raw_text(self.segments(l.span, &text)?);
+            } else {
+                self.out
+                    .push_text(self.segments(l.span, &text)?, Escape::Text);
+            }
+        }
+        if let Some(children) = &elem.children {
+            self.render_ast(children)?;
+        }
+        Ok(())
+    }
+
+    fn render_comment(&mut self, comment: &Comment) -> Result<()> {
+        if comment.is_silent() {
+            return Ok(());
+        }
+        self.start_line();
+        self.out.push_str("<!--");
+        for segment in self.segments(comment.span, &comment.text)? {
+            self.out.push(segment.into());
+        }
+        self.out.push_str("-->");
+        Ok(())
+    }
+}
+
+///Estimates the length of the rendered nodes, without the
+///included templates, to reserve the output buffer once.
+fn size_hint(ast: &[Node]) -> usize {
+    ast.iter()
+        .map(|node| match node {
+            Node::ELEM(elem) => {
+                let attrs: usize = elem
+                    .attr
+                    .iter()
+                    .flatten()
+                    .map(|a| a.name.len() + a.value.len() + 4)
+                    .sum();
+                let classes: usize = elem.classes.iter().flatten().map(|c| c.len() + 9).sum();
+                let cont = match &elem.cont {
+                    Some(Cont::LINE(l)) => l.text.len(),
+                    Some(Cont::BLOCK(b)) => b.iter().map(|l| l.text.len() + 4).sum(),
+                    None => 0,
+                };
+                let children = elem.children.as_deref().map_or(0, size_hint);
+                2 * elem.tag.len()
+                    + 5
+                    + elem.id.as_ref().map_or(0, |id| id.len() + 6)
+                    + classes
+                    + attrs
+                    + cont
+                    + children
+            }
+            Node::COMMENT(comment) if !comment.is_silent() => comment.text.len() + 7,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn collect_parent_vars<'a>(ast: &'a [Node], used: &mut BTreeSet<&'a str>) {
+    for node in ast {
+        match node {
+            Node::ELEM(elem) => {
+                for a in elem.attr.iter().flatten() {
+                    used.extend(text::parent_vars(&a.value));
+                }
+                match &elem.cont {
+                    Some(Cont::LINE(l)) => used.extend(text::parent_vars(&l.text)),
+                    Some(Cont::BLOCK(b)) => {
+                        for l in b {
+                            used.extend(text::parent_vars(&l.text));
+                        }
+                    }
+                    None => (),
+                }
+            }
+            Node::PV(var) => used.extend(text::parent_vars(&var.value.text)),
+            _ => (),
+        }
+        for body in node.bodies() {
+            collect_parent_vars(body, used);
+        }
+    }
+}
+
+pub type Result<T, E = RenderError> = std::result::Result<T, E>;
+
+/// RenderError enumerates all errors that can occur while rendering a template.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RenderError {
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+    #[error("Couldn't load template {path}: {source}")]
+    Template {
+        path: String,
+        source: TemplateManagerError,
+    },
+    /// An error inside a template that was included at `line` and `col`
+    #[error("In template {path} included at line {line}, column {col}: {source}")]
+    Include {
+        path: String,
+        line: usize,
+        col: usize,
+        source: Box<RenderError>,
+    },
+    /// An error inside the parent template declared at `line` and `col`
+    #[error("In parent template {path} declared at line {line}, column {col}: {source}")]
+    Parent {
+        path: String,
+        line: usize,
+        col: usize,
+        source: Box<RenderError>,
+    },
+    #[error("Parent variable {0} is not assigned by the child template")]
+    MissingParentVar(String),
+    #[error("Parent variable {0} is assigned but not used by the parent template")]
+    UnknownParentVar(String),
+    #[error("The parent template has no =>child to insert the child template at")]
+    MissingChild,
+    #[error("Void element {tag} at line {line}, column {col} can't have content")]
+    VoidContent {
+        tag: String,
+        line: usize,
+        col: usize,
+    },
+    /// The templates that include or extend each other, starting and ending with the same one
+    #[error("Template cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("Variable {0} is not in the context")]
+    MissingVar(String),
+    #[error("Invalid expression `{expr}`: {message}")]
+    Expr { expr: String, message: String },
+    /// A value couldn't be serialized, only with the `serde` feature
+    #[error("Couldn't use the value as a context: {0}")]
+    Serialize(String),
+    #[error("Couldn't write the html")]
+    Write(#[from] fmt::Error),
+    #[error("Couldn't write the html: {0}")]
+    Io(IoError),
+}
+
+///The error of a writer. Errors of the same kind are equal,
+///so that `RenderError` can still be compared.
+#[derive(Debug, Clone)]
+pub struct IoError(pub Arc<io::Error>);
+
+impl PartialEq for IoError {
+    fn eq(&self, other: &IoError) -> bool {
+        self.0.kind() == other.0.kind()
+    }
 }
 
-fn render_elem_content(cont: &Cont) -> String {
-    match cont {
-        Cont::LINE(l) => l.to_string(),
-        Cont::BLOCK(b) => b.join("<br>"),
+impl Eq for IoError {}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::ast_types::{Attr, Elem};
+    use crate::parser::ast_types::Attr;
     use crate::{node_el_vec, string_vec};
 
     macro_rules! test_elems {
         ([ $($input:expr),+ ], $expected:literal ) => {
-            assert_eq!(render_ast(vec![$(Node::ELEM($input)),+]), $expected);
+            let mut tm = TemplateManager::default();
+            let options = RenderOptions::default();
+            let mut renderer = Renderer::new(&mut tm, &options);
+            renderer.render_ast(&[$(Node::ELEM($input)),+]).unwrap();
+            assert_eq!(renderer.out.to_string(), $expected);
+        }
+    }
+
+    fn manager(templates: &[(&str, &str)]) -> TemplateManager {
+        let mut tm = TemplateManager::default();
+        for (path, template) in templates {
+            tm.add(path.to_string(), template.to_string());
         }
+        tm
     }
 
     #[test]
     fn renders_simple_tag() {
-        test_elems!([Elem::from_ta("hello")], "<hello />");
+        test_elems!([Elem::from_ta("hello")], "<hello></hello>");
     }
 
     #[test]
     fn renders_two_tags() {
         test_elems!(
             [Elem::from_ta("hello"), Elem::from_ta("world")],
-            "<hello /><world />"
+            "<hello></hello><world></world>"
         );
     }
 
@@ -121,7 +876,7 @@ mod tests {
                 "hello",
                 node_el_vec![Elem::from_ta("world")]
             )],
-            "<hello><world /></hello>"
+            "<hello><world></world></hello>"
         );
     }
 
@@ -129,7 +884,7 @@ mod tests {
     fn renders_id() {
         test_elems!(
             [Elem::from_ta_id("hello", "world")],
-            "<hello id=\"world\" />"
+            "<hello id=\"world\"></hello>"
         );
     }
 
@@ -137,7 +892,7 @@ mod tests {
     fn renders_classes() {
         test_elems!(
             [Elem::from_ta_cl("hello", string_vec!["world", "universe"])],
-            "<hello class=\"world universe\" />"
+            "<hello class=\"world universe\"></hello>"
         );
     }
 
@@ -149,15 +904,17 @@ mod tests {
                 vec![
                     Attr {
                         name: "world".to_string(),
-                        value: "great".to_string()
+                        value: "great".to_string(),
+                        ..Attr::default()
                     },
                     Attr {
                         name: "sun".to_string(),
-                        value: "shining".to_string()
+                        value: "shining".to_string(),
+                        ..Attr::default()
                     }
                 ]
             )],
-            "<hello world=\"great\" sun=\"shining\" />"
+            "<hello world=\"great\" sun=\"shining\"></hello>"
         );
     }
 
@@ -168,12 +925,64 @@ mod tests {
                 "img",
                 vec![Attr {
                     name: "Mr".to_string(),
-                    value: "Thomas \"Neo\" Anderson".to_string()
+                    value: "Thomas \"Neo\" Anderson".to_string(),
+                    ..Attr::default()
                 }]
             )],
-            "<img Mr='Thomas \"Neo\" Anderson' />"
+            "<img Mr=\"Thomas &quot;Neo&quot; Anderson\">"
+        );
+    }
+    #[test]
+    fn escapes_static_text_and_attributes() {
+        let source = "p title='Say \"hi\" & bye' Fish &amp; Chips & <peas>\np:\n  1 < 2\n  &nbsp;&#8212;\n/! <b> & stays";
+        let output = render_source(source, &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output,
+            "<p title=\"Say &quot;hi&quot; &amp; bye\">Fish &amp; Chips &amp; &lt;peas&gt;</p>\
+             <p>1 &lt; 2<br>&nbsp;&#8212;</p><!--<b> & stays-->"
+        );
+    }
+
+    #[test]
+    fn renders_vars_in_attributes_ids_and_classes() {
+        let source = "a href={{url}} title={{{ title }}}-x Go\nli#item-{{ id }}.card.card-{{variant}}\ndiv.card class=\"{{variant}} big\"\np class=\"{{a}}\"";
+        let output = compile_source(source, &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output.to_string(),
+            "<a href=\"{{url}}\" title=\"{{{title}}}-x\">Go</a>\
+             <li id=\"item-{{id}}\" class=\"card card-{{variant}}\"></li>\
+             <div class=\"card {{variant}} big\"></div><p class=\"{{a}}\"></p>"
+        );
+        let escapes: Vec<_> = output
+            .parts()
+            .iter()
+            .filter_map(|part| match part {
+                Part::Var { escape, .. } => Some(*escape),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            escapes,
+            [
+                Escape::Attr,
+                Escape::Raw,
+                Escape::Attr,
+                Escape::Attr,
+                Escape::Attr,
+                Escape::Attr
+            ]
         );
     }
+
+    #[test]
+    fn merges_all_class_attributes() {
+        let options = RenderOptions::default();
+        let output = render_source("div.a class=\"b\" class=\"c\"", &options).unwrap();
+        assert_eq!(output, "<div class=\"a b c\"></div>");
+        let output = render_source("p id=x class=b title=t class=c", &options).unwrap();
+        assert_eq!(output, "<p id=\"x\" class=\"b c\" title=\"t\"></p>");
+    }
+
     #[test]
     fn renders_attributes_on_children() {
         test_elems!(
@@ -181,17 +990,401 @@ mod tests {
                 "hello",
                 vec![Attr {
                     name: "world".to_string(),
-                    value: "great".to_string()
+                    value: "great".to_string(),
+                    ..Attr::default()
                 }],
                 node_el_vec![Elem::from_ta_at(
                     "how",
                     vec![Attr {
                         name: "are".to_string(),
-                        value: "you?".to_string()
+                        value: "you?".to_string(),
+                        ..Attr::default()
                     }]
                 )]
             )],
-            "<hello world=\"great\"><how are=\"you?\" /></hello>"
+            "<hello world=\"great\"><how are=\"you?\"></how></hello>"
+        );
+    }
+
+    #[test]
+    fn renders_only_html_comments() {
+        let output = render_source(
+            "// silent\ndiv\n  /! loud\n  /* also\n     silent */",
+            &RenderOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(output, "<div><!--loud--></div>");
+    }
+
+    #[test]
+    fn renders_includes_inline() {
+        let mut tm = manager(&[
+            ("page", "body\n  >navbar\n  div main\n>footer"),
+            ("navbar", "nav\n  a href=\"/blog/\" Blog"),
+            ("footer", "footer bye"),
+        ]);
+        let output = render_template(&mut tm, "page", &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output,
+            "<body><nav><a href=\"/blog/\">Blog</a></nav><div>main</div></body><footer>bye</footer>"
+        );
+    }
+
+    #[test]
+    fn renders_nested_includes() {
+        let mut tm = manager(&[("a", ">b"), ("b", "p\n  >c"), ("c", "br")]);
+        assert_eq!(
+            render_template(&mut tm, "a", &RenderOptions::default()).unwrap(),
+            "<p><br></p>"
+        );
+    }
+
+    #[test]
+    fn detects_include_cycles() {
+        let mut tm = manager(&[("a", "div\n  >b"), ("b", ">c"), ("c", ">a")]);
+        let err = render_template(&mut tm, "a", &RenderOptions::default()).unwrap_err();
+        assert_eq!(err, RenderError::Cycle(string_vec!["a", "b", "c", "a"]));
+        assert_eq!(err.to_string(), "Template cycle: a -> b -> c -> a");
+    }
+
+    #[test]
+    fn reports_missing_includes() {
+        let mut tm = manager(&[("page", "div\n  >missing/navbar")]);
+        let err = render_template(&mut tm, "page", &RenderOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Include {
+                path: "missing/navbar".to_string(),
+                line: 2,
+                col: 3,
+                source: Box::new(RenderError::Template {
+                    path: "missing/navbar".to_string(),
+                    source: TemplateManagerError::TemplateNotFound
+                })
+            }
+        );
+    }
+
+    #[test]
+    fn renders_child_into_parent() {
+        let mut tm = manager(&[
+            (
+                "parent",
+                "head\n  title {pv{title}} | Blog\nbody\n  >navbar\n  =>child",
+            ),
+            ("navbar", "nav menu"),
+            (
+                "child",
+                "<!parent\n<pv title Awesome Page\ndiv#content\n  h1 Awesome Page",
+            ),
+        ]);
+        let output = render_template(&mut tm, "child", &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output,
+            "<head><title>Awesome Page | Blog</title></head>\
+             <body><nav>menu</nav><div id=\"content\"><h1>Awesome Page</h1></div></body>"
+        );
+    }
+
+    #[test]
+    fn renders_multiple_levels_of_parents() {
+        let mut tm = manager(&[
+            ("base", "html lang=\"{pv{lang}}\"\n  =>child"),
+            ("blog", "<!base\n<pv lang {pv{lang}}\nmain\n  =>child"),
+            ("post", "<!blog\n<pv lang en\np Hello"),
+        ]);
+        let output = render_template(&mut tm, "post", &RenderOptions::default()).unwrap();
+        assert_eq!(output, "<html lang=\"en\"><main><p>Hello</p></main></html>");
+    }
+
+    #[test]
+    fn reports_unassigned_parent_vars() {
+        let mut tm = manager(&[
+            ("parent", "title {pv{title}}\n=>child"),
+            ("child", "<!parent\np Hello"),
+        ]);
+        let err = render_template(&mut tm, "child", &RenderOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Parent {
+                path: "parent".to_string(),
+                line: 1,
+                col: 1,
+                source: Box::new(RenderError::MissingParentVar("title".to_string()))
+            }
+        );
+    }
+
+    #[test]
+    fn reports_parents_without_child() {
+        let mut tm = manager(&[("parent", "main"), ("child", "<!parent\np Hello")]);
+        let err = render_template(&mut tm, "child", &RenderOptions::default()).unwrap_err();
+        match err {
+            RenderError::Parent { source, .. } => assert_eq!(*source, RenderError::MissingChild),
+            _ => panic!("expected an error in the parent template"),
+        }
+    }
+
+    #[test]
+    fn reports_unused_parent_vars() {
+        let mut tm = manager(&[
+            ("parent", "=>child"),
+            ("child", "<!parent\n<pv titel Hello\np Hello"),
+        ]);
+        let err = render_template(&mut tm, "child", &RenderOptions::default()).unwrap_err();
+        match err {
+            RenderError::Parent { source, .. } => {
+                assert_eq!(*source, RenderError::UnknownParentVar("titel".to_string()))
+            }
+            _ => panic!("expected an error in the parent template"),
+        }
+    }
+
+    #[test]
+    fn detects_parent_cycles() {
+        let mut tm = manager(&[("a", "<!b\n=>child"), ("b", "<!a\n=>child")]);
+        let err = render_template(&mut tm, "a", &RenderOptions::default()).unwrap_err();
+        assert_eq!(err, RenderError::Cycle(string_vec!["a", "b", "a"]));
+    }
+
+    #[test]
+    fn renders_pretty() {
+        let source = "div#greeting\n  h1 Hello\n  p:\n    how\n    are you?\n  ul\n    li\n      a href=\"/\" Home\n  br";
+        let output = render_source(source, &RenderOptions::pretty()).unwrap();
+        let expected = "<div id=\"greeting\">
+  <h1>Hello</h1>
+  <p>how<br>are you?</p>
+  <ul>
+    <li>
+      <a href=\"/\">Home</a>
+    </li>
+  </ul>
+  <br>
+</div>";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn parses_with_the_indent_option() {
+        let source = "div\n    p\n      span";
+        let options = RenderOptions {
+            parse_indent: Indent::Spaces(4),
+            ..RenderOptions::default()
+        };
+        let err = render_source(source, &options).unwrap_err();
+        assert!(matches!(err, RenderError::Parse(e) if e.line == 3));
+        let output = render_source("div\n\tp", &options);
+        assert!(output.is_err());
+        let options = RenderOptions {
+            parse_indent: Indent::Tabs,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_source("div\n\tp", &options).unwrap(),
+            "<div><p></p></div>"
+        );
+    }
+
+    #[test]
+    fn renders_pretty_content_and_children() {
+        let options = RenderOptions {
+            indent: "\t".to_string(),
+            ..RenderOptions::pretty()
+        };
+        let output = render_source("p Hello\n  span World\n/! done", &options).unwrap();
+        assert_eq!(
+            output,
+            "<p>\n\tHello\n\t<span>World</span>\n</p>\n<!--done-->"
+        );
+    }
+
+    #[test]
+    fn keeps_whitespace_sensitive_elements_compact() {
+        let source = "div\n  pre\n    code\n      span a\n  textarea hi";
+        let output = render_source(source, &RenderOptions::pretty()).unwrap();
+        assert_eq!(
+            output,
+            "<div>\n  <pre><code><span>a</span></code></pre>\n  <textarea>hi</textarea>\n</div>"
+        );
+    }
+
+    #[test]
+    fn ignores_the_case_of_whitespace_sensitive_elements() {
+        let output = render_source("PRE\n  code a", &RenderOptions::pretty()).unwrap();
+        assert_eq!(output, "<PRE><code>a</code></PRE>");
+        let options = RenderOptions {
+            dialect: Dialect::Xml,
+            ..RenderOptions::pretty()
+        };
+        let output = render_source("PRE\n  code a", &options).unwrap();
+        assert_eq!(output, "<PRE>\n  <code>a</code>\n</PRE>");
+    }
+
+    #[test]
+    fn renders_pretty_includes_and_children() {
+        let mut tm = manager(&[
+            ("parent", "html\n  body\n    >nav\n    =>child"),
+            ("nav", "nav\n  a Blog"),
+            ("child", "<!parent\ndiv\n  h1 Hi"),
+        ]);
+        let output = render_template(&mut tm, "child", &RenderOptions::pretty()).unwrap();
+        let expected = "<html>
+  <body>
+    <nav>
+      <a>Blog</a>
+    </nav>
+    <div>
+      <h1>Hi</h1>
+    </div>
+  </body>
+</html>";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn renders_void_elements_by_dialect() {
+        let source = "img src=\"a.png\"\nbr\ndiv\nscript src=\"app.js\"\np:\n  a\n  b";
+        let output = render_source(source, &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output,
+            "<img src=\"a.png\"><br><div></div><script src=\"app.js\"></script><p>a<br>b</p>"
+        );
+        let options = RenderOptions {
+            dialect: Dialect::Xhtml,
+            ..RenderOptions::default()
+        };
+        let output = render_source(source, &options).unwrap();
+        assert_eq!(
+            output,
+            "<img src=\"a.png\" /><br /><div></div><script src=\"app.js\"></script><p>a<br />b</p>"
+        );
+    }
+
+    #[test]
+    fn ignores_case_of_void_elements() {
+        let output = render_source("BR\nImg src=\"a.png\"", &RenderOptions::default()).unwrap();
+        assert_eq!(output, "<BR><Img src=\"a.png\">");
+        let err = render_source("BR text", &RenderOptions::default()).unwrap_err();
+        assert!(matches!(err, RenderError::VoidContent { .. }));
+    }
+
+    #[test]
+    fn keeps_raw_text_elements() {
+        let source = "script if (a < b && c) {{ x }}\nstyle:\n  a > b {}\n  p {}";
+        let output = render_source(source, &RenderOptions::default()).unwrap();
+        assert_eq!(
+            output,
+            "<script>if (a < b && c) {{x}}</script><style>a > b {}\np {}</style>"
+        );
+        let output = compile_source("script {{ x }}", &RenderOptions::default()).unwrap();
+        let context = crate::context::Context::new().with("x", "</script>");
+        assert_eq!(
+            crate::interpreter::render(&output, &context).unwrap(),
+            "<script>&lt;/script&gt;</script>"
+        );
+    }
+
+    #[test]
+    fn renders_xml() {
+        let source = "svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" viewBox=\"0 0 10 10\"\n  defs\n    linearGradient#fade\n  use xlink:href=\"#icon\"\n  svg:rect width=10\n  br";
+        let options = RenderOptions {
+            dialect: Dialect::Xml,
+            ..RenderOptions::default()
+        };
+        let output = render_source(source, &options).unwrap();
+        assert_eq!(
+            output,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" viewBox=\"0 0 10 10\">\
+             <defs><linearGradient id=\"fade\"/></defs>\
+             <use xlink:href=\"#icon\"/><svg:rect width=\"10\"/><br/></svg>"
+        );
+    }
+
+    #[test]
+    fn keeps_xml_content_lines() {
+        let options = RenderOptions {
+            dialect: Dialect::Xml,
+            ..RenderOptions::default()
+        };
+        let output = render_source("summary:\n  a\n  b", &options).unwrap();
+        assert_eq!(output, "<summary>a\nb</summary>");
+    }
+
+    #[test]
+    fn allows_content_in_xml_void_names() {
+        let options = RenderOptions {
+            dialect: Dialect::Xml,
+            ..RenderOptions::default()
+        };
+        let output = render_source("link https://example.com", &options).unwrap();
+        assert_eq!(output, "<link>https://example.com</link>");
+    }
+
+    #[test]
+    fn parses_dialect_names() {
+        assert_eq!("HTML5".parse(), Ok(Dialect::Html5));
+        assert_eq!("xhtml".parse(), Ok(Dialect::Xhtml));
+        assert_eq!("xml".parse(), Ok(Dialect::Xml));
+        assert!("sgml".parse::<Dialect>().is_err());
+    }
+
+    #[test]
+    fn rejects_content_in_void_elements() {
+        let err = render_source("div\n  br hello", &RenderOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::VoidContent {
+                tag: "br".to_string(),
+                line: 2,
+                col: 3
+            }
+        );
+    }
+
+    #[test]
+    fn renders_into_writers() {
+        let mut tm = manager(&[("page", "ul\n  >item\n  >item"), ("item", "li {{name}}")]);
+        let mut html = String::from("<!DOCTYPE html>");
+        render_into(&mut tm, "page", &RenderOptions::default(), &mut html).unwrap();
+        assert_eq!(
+            html,
+            "<!DOCTYPE html><ul><li>{{name}}</li><li>{{name}}</li></ul>"
+        );
+        let mut bytes = Vec::new();
+        render_into_io(&mut tm, "item", &RenderOptions::default(), &mut bytes).unwrap();
+        assert_eq!(bytes, b"<li>{{name}}</li>");
+        let mut full = [0u8; 4];
+        let err = render_into_io(
+            &mut tm,
+            "item",
+            &RenderOptions::default(),
+            &mut &mut full[..],
+        )
+        .unwrap_err();
+        match err {
+            RenderError::Io(IoError(e)) => assert_eq!(e.kind(), io::ErrorKind::WriteZero),
+            _ => panic!("expected a write error"),
+        }
+    }
+
+    #[test]
+    fn passes_up_parse_errors() {
+        let err = render_source("div\n  p.", &RenderOptions::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            RenderError::Parse(ParseError { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn passes_up_missing_templates() {
+        let err = render_path("does/not/exist.slimr", &RenderOptions::default()).unwrap_err();
+        assert_eq!(
+            err,
+            RenderError::Template {
+                path: "does/not/exist.slimr".to_string(),
+                source: TemplateManagerError::TemplateNotFound
+            }
         );
     }
 }
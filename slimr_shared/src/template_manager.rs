@@ -4,21 +4,27 @@ use std::collections::BTreeMap;
 ///If you request a template via its path/name,
 ///and it's not in the map, the manager will look
 ///for a file at the path relative to the working directory.
+///Paths without an extension also match files ending in `.slimr`.
 #[derive(Debug, Default)]
 pub struct TemplateManager {
     templates: BTreeMap<String, String>,
 }
 
 impl TemplateManager {
-    pub fn add(self: &mut Self, path: String, template: String) {
+    pub fn add(&mut self, path: String, template: String) {
         self.templates
             .insert(path.to_string(), template.to_string());
     }
 
-    pub fn get(self: &mut Self, path: &str) -> Result<String> {
+    pub fn get(&mut self, path: &str) -> Result<String> {
         match self.templates.get(path) {
             Some(t) => Ok(t.to_string()),
-            None => read_template_file(path),
+            None => {
+                read_template_file(path).or_else(|e| match std::path::Path::new(path).extension() {
+                    None => read_template_file(&format!("{}.slimr", path)),
+                    Some(_) => Err(e),
+                })
+            }
         }
     }
 }
@@ -59,6 +65,14 @@ mod tests {
         assert_eq!("hello world".to_string(), manager.get("path").unwrap());
     }
 
+    #[test]
+    fn adds_slimr_extension_to_files() {
+        let mut manager = TemplateManager::default();
+        let with_ext = manager.get("../testing/templates/basic_tag.slimr").unwrap();
+        let without_ext = manager.get("../testing/templates/basic_tag").unwrap();
+        assert_eq!(with_ext, without_ext);
+    }
+
     #[test]
     fn returns_error_on_invalid_path() {
         let mut manager = TemplateManager::default();
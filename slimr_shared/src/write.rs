@@ -0,0 +1,58 @@
+use std::{fmt, io};
+
+///Lets the html be written into an `io::Write` like a file or a socket.
+///Rendering only needs `fmt::Write`, so the io error is kept aside
+///until the rendering is finished.
+pub struct IoWriter<'a, W: ?Sized> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<'a, W: io::Write + ?Sized> IoWriter<'a, W> {
+    pub fn new(inner: &'a mut W) -> IoWriter<'a, W> {
+        IoWriter { inner, error: None }
+    }
+
+    ///Turns the result of the rendering into the io error that caused it.
+    pub fn finish(self, result: fmt::Result) -> io::Result<()> {
+        match (result, self.error) {
+            (_, Some(e)) => Err(e),
+            (Ok(()), None) => Ok(()),
+            (Err(_), None) => Err(io::Error::other("formatting the html failed")),
+        }
+    }
+}
+
+impl<W: io::Write + ?Sized> fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn writes_into_io() {
+        let mut bytes = Vec::new();
+        let mut writer = IoWriter::new(&mut bytes);
+        let result = write!(writer, "<p>{}</p>", 1);
+        writer.finish(result).unwrap();
+        assert_eq!(bytes, b"<p>1</p>");
+    }
+
+    #[test]
+    fn keeps_io_errors() {
+        let mut buf = [0u8; 2];
+        let mut slice = &mut buf[..];
+        let mut writer = IoWriter::new(&mut slice);
+        let result = writer.write_str("<p>");
+        let err = writer.finish(result).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    }
+}
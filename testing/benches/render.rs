@@ -0,0 +1,93 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use slimr::{Dialect, RenderOptions, SlimR, TemplateManager};
+
+const TEMPLATES: [(&str, &str); 4] = [
+    (
+        "bench/layout",
+        include_str!("../templates/bench/layout.slimr"),
+    ),
+    (
+        "bench/header",
+        include_str!("../templates/bench/header.slimr"),
+    ),
+    (
+        "bench/product",
+        include_str!("../templates/bench/product.slimr"),
+    ),
+    ("bench/page", include_str!("../templates/bench/page.slimr")),
+];
+
+fn manager() -> TemplateManager {
+    let mut manager = TemplateManager::default();
+    for (path, template) in TEMPLATES {
+        manager.add(path.to_string(), template.to_string());
+    }
+    manager
+}
+
+///Renders the page and checks that the output didn't change
+///before measuring it.
+fn bench_page(c: &mut Criterion, name: &str, options: RenderOptions, expected: &str) {
+    let mut manager = manager();
+    let output = slimr::render_static_template(&mut manager, "bench/page", &options).unwrap();
+    assert_eq!(output, expected, "the rendered {} page changed", name);
+    c.bench_function(name, |b| {
+        b.iter(|| slimr::render_static_template(&mut manager, black_box("bench/page"), &options))
+    });
+}
+
+fn renderer(c: &mut Criterion) {
+    bench_page(
+        c,
+        "render page",
+        RenderOptions::default(),
+        include_str!("../templates/bench/page.html"),
+    );
+    bench_page(
+        c,
+        "render pretty page",
+        RenderOptions::pretty(),
+        include_str!("../templates/bench/page.pretty.html"),
+    );
+    bench_page(
+        c,
+        "render pretty xml page",
+        RenderOptions {
+            dialect: Dialect::Xml,
+            ..RenderOptions::pretty()
+        },
+        include_str!("../templates/bench/page.xml"),
+    );
+}
+
+#[derive(SlimR)]
+#[template(path = "testing/templates/bench/product.slimr")]
+struct Product<'a> {
+    id: u32,
+    name: &'a str,
+    price: f32,
+    description: &'a str,
+}
+
+fn derived(c: &mut Criterion) {
+    let products: Vec<_> = (0..100)
+        .map(|id| Product {
+            id,
+            name: "Fish & Chips",
+            price: 9.5,
+            description: "<b>crispy</b>",
+        })
+        .collect();
+    c.bench_function("render 100 derived products", |b| {
+        b.iter(|| {
+            let mut html = String::new();
+            for p in &products {
+                p.render_into(&mut html).unwrap();
+            }
+            html
+        })
+    });
+}
+
+criterion_group!(benches, renderer, derived);
+criterion_main!(benches);
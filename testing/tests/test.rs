@@ -49,3 +49,585 @@ fn reads_slimr_file() {
     let t = Title { name: "World" };
     assert_eq!(t.render(), "<h1>Hello World</h1>");
 }
+
+#[test]
+fn renders_includes() {
+    #[derive(SlimR)]
+    #[template(path = "testing/templates/with_include.slimr")]
+    struct Page<'a> {
+        name: &'a str,
+    }
+    let p = Page { name: "World" };
+    assert_eq!(
+        p.render(),
+        "<body><nav><a href=\"/blog/\">Blog</a></nav><h1>Hello World</h1></body>"
+    );
+}
+
+#[test]
+fn renders_child_into_parent() {
+    #[derive(SlimR)]
+    #[template(path = "testing/templates/layout/child.slimr")]
+    struct Page<'a> {
+        name: &'a str,
+    }
+    let p = Page { name: "Neo" };
+    assert_eq!(
+        p.render(),
+        "<head><title>Neo's Page</title></head><body>\
+         <nav><a href=\"/blog/\">Blog</a></nav>\
+         <div id=\"content\"><h1>Hello Neo</h1></div></body>"
+    );
+}
+
+#[test]
+fn renders_pretty() {
+    #[derive(SlimR)]
+    #[template(
+        source = "ul\n  li {{first}}\n  li {{second}}",
+        pretty,
+        indent = "    "
+    )]
+    struct List<'a> {
+        first: &'a str,
+        second: &'a str,
+    }
+    let l = List {
+        first: "one",
+        second: "two",
+    };
+    assert_eq!(
+        l.render(),
+        "<ul>\n    <li>one</li>\n    <li>two</li>\n</ul>"
+    );
+}
+
+#[test]
+fn renders_xml_dialect() {
+    #[derive(SlimR)]
+    #[template(
+        source = "svg xmlns:xlink=\"http://www.w3.org/1999/xlink\"\n  use xlink:href=\"#{{icon}}\"",
+        dialect = "xml"
+    )]
+    struct Icon<'a> {
+        icon: &'a str,
+    }
+    let i = Icon { icon: "star" };
+    assert_eq!(
+        i.render(),
+        "<svg xmlns:xlink=\"http://www.w3.org/1999/xlink\"><use xlink:href=\"#star\"/></svg>"
+    );
+}
+
+#[test]
+fn parses_with_the_indent_attribute() {
+    #[derive(SlimR)]
+    #[template(
+        source = "ul\n\tli {{first}}\n\tli:\n\t\tone\n\t\t  two",
+        parse_indent = "tabs"
+    )]
+    struct List<'a> {
+        first: &'a str,
+    }
+    let l = List { first: "one" };
+    assert_eq!(l.render(), "<ul><li>one</li><li>one<br>  two</li></ul>");
+}
+
+#[test]
+fn escapes_values() {
+    #[derive(SlimR)]
+    #[template(source = "a title=\"{{title}}\" {{name}} {{{ icon }}}")]
+    struct Link<'a> {
+        title: &'a str,
+        name: &'a str,
+        icon: &'a str,
+    }
+    let l = Link {
+        title: "\"quoted\" & 'single'",
+        name: "<script>",
+        icon: "<i></i>",
+    };
+    assert_eq!(
+        l.render(),
+        "<a title=\"&quot;quoted&quot; &amp; &#39;single&#39;\">&lt;script&gt; <i></i></a>"
+    );
+}
+
+#[test]
+fn keeps_single_braces() {
+    #[derive(SlimR)]
+    #[template(source = "code fn main() { {{body}} }")]
+    struct Code<'a> {
+        body: &'a str,
+    }
+    let c = Code { body: "a < b" };
+    assert_eq!(c.render(), "<code>fn main() { a &lt; b }</code>");
+}
+
+#[test]
+fn renders_into_writers() {
+    #[derive(SlimR)]
+    #[template(source = "li {{item}}")]
+    struct Item<'a> {
+        item: &'a str,
+    }
+    let mut html = String::from("<ul>");
+    for item in ["a", "b&c"] {
+        Item { item }.render_into(&mut html).unwrap();
+    }
+    html.push_str("</ul>");
+    assert_eq!(html, "<ul><li>a</li><li>b&amp;c</li></ul>");
+
+    let mut bytes = Vec::new();
+    Item { item: "<io>" }.render_into_io(&mut bytes).unwrap();
+    assert_eq!(bytes, b"<li>&lt;io&gt;</li>");
+}
+
+#[test]
+fn renders_bench_page_unchanged() {
+    let mut manager = slimr::TemplateManager::default();
+    for name in ["layout", "header", "product", "page"] {
+        let path = format!("templates/bench/{}.slimr", name);
+        let template = std::fs::read_to_string(path).unwrap();
+        manager.add(format!("bench/{}", name), template);
+    }
+    let outputs = [
+        ("page.html", slimr::RenderOptions::default()),
+        ("page.pretty.html", slimr::RenderOptions::pretty()),
+        (
+            "page.xml",
+            slimr::RenderOptions {
+                dialect: slimr::Dialect::Xml,
+                ..slimr::RenderOptions::pretty()
+            },
+        ),
+    ];
+    for (file, options) in outputs {
+        let expected = std::fs::read_to_string(format!("templates/bench/{}", file)).unwrap();
+        let output = slimr::render_static_template(&mut manager, "bench/page", &options).unwrap();
+        assert_eq!(output, expected, "{} changed", file);
+    }
+}
+
+#[test]
+fn renders_nested_fields() {
+    struct Address {
+        city: &'static str,
+    }
+    struct User {
+        name: String,
+        address: Address,
+    }
+    #[derive(SlimR)]
+    #[template(
+        source = "p {{user.name}} lives in {{ user.address.city }}, {{point.0}}/{{point.1}}"
+    )]
+    struct Profile {
+        user: User,
+        point: (i32, i32),
+    }
+    let p = Profile {
+        user: User {
+            name: "Neo".into(),
+            address: Address { city: "Zion" },
+        },
+        point: (3, 4),
+    };
+    assert_eq!(p.render(), "<p>Neo lives in Zion, 3/4</p>");
+}
+
+#[test]
+fn renders_serializable_context() {
+    #[derive(serde::Serialize)]
+    struct Address {
+        city: String,
+    }
+    #[derive(serde::Serialize)]
+    struct User {
+        name: String,
+        address: Address,
+        friends: Vec<&'static str>,
+    }
+    let user = User {
+        name: "<Neo>".into(),
+        address: Address {
+            city: "Zion".into(),
+        },
+        friends: vec!["Trinity", "Morpheus"],
+    };
+    let context = slimr::Context::from_serialize(&user).unwrap();
+    let html = slimr::render_template_str(
+        "p {{name}} from {{address.city}} knows {{friends}}, mostly {{friends.0}}",
+        context,
+    )
+    .unwrap();
+    assert_eq!(
+        html,
+        "<p>&lt;Neo&gt; from Zion knows Trinity, Morpheus, mostly Trinity</p>"
+    );
+}
+
+#[test]
+fn renders_vars_in_attributes() {
+    #[derive(SlimR)]
+    #[template(
+        source = "div#user-{{id}}.card.card-{{variant}} class=\"{{extra}}\"\n  a href={{url}} Profile\n  a href=\"/users/{{id}}\" Posts"
+    )]
+    struct Card<'a> {
+        id: u32,
+        variant: &'a str,
+        extra: &'a str,
+        url: &'a str,
+    }
+    let c = Card {
+        id: 7,
+        variant: "dark",
+        extra: "\" onmouseover=\"evil()",
+        url: "/u?a=1&b=2",
+    };
+    assert_eq!(
+        c.render(),
+        "<div id=\"user-7\" class=\"card card-dark &quot; onmouseover=&quot;evil()\">\
+         <a href=\"/u?a=1&amp;b=2\">Profile</a><a href=\"/users/7\">Posts</a></div>"
+    );
+    let context = slimr::Context::new()
+        .with("id", 7)
+        .with("variant", "<dark>")
+        .with("extra", "x")
+        .with("url", "/u");
+    let html = slimr::render_template_str(
+        "div#user-{{id}}.card-{{variant}} class=\"{{extra}}\"\n  a href={{url}} Profile",
+        context,
+    )
+    .unwrap();
+    assert_eq!(
+        html,
+        "<div id=\"user-7\" class=\"card-&lt;dark&gt; x\"><a href=\"/u\">Profile</a></div>"
+    );
+}
+
+#[test]
+fn renders_conditions() {
+    #[derive(SlimR)]
+    #[template(
+        source = "nav\n  - if user.is_none()\n    a href=\"/login\" Log in\n  - else if unread > 0 && !muted\n    span {{unread}} new\n  - else\n    span No news\n  - if muted\n    span Muted"
+    )]
+    struct Nav {
+        user: Option<String>,
+        unread: u32,
+        muted: bool,
+    }
+    let nav = |user: Option<&str>, unread, muted| Nav {
+        user: user.map(Into::into),
+        unread,
+        muted,
+    };
+    assert_eq!(
+        nav(None, 0, false).render(),
+        "<nav><a href=\"/login\">Log in</a></nav>"
+    );
+    assert_eq!(
+        nav(Some("Neo"), 2, false).render(),
+        "<nav><span>2 new</span></nav>"
+    );
+    assert_eq!(
+        nav(Some("Neo"), 2, true).render(),
+        "<nav><span>No news</span><span>Muted</span></nav>"
+    );
+    let context = slimr::Context::new()
+        .with("user", "Neo")
+        .with("unread", 2)
+        .with("muted", false);
+    let html = slimr::render_template_str(
+        "nav\n  - if !user\n    a Log in\n  - else if unread > 0 && !muted\n    span {{unread}} new",
+        context,
+    )
+    .unwrap();
+    assert_eq!(html, "<nav><span>2 new</span></nav>");
+}
+
+#[test]
+fn renders_loops() {
+    struct User {
+        name: String,
+        tags: Vec<&'static str>,
+    }
+    #[derive(SlimR)]
+    #[template(
+        source = "table\n  - for user in users\n    tr class=\"row-{{loop.index}}\"\n      td {{user.name}}\n      - for tag in &user.tags\n        - if !loop.first\n          td ,\n        td {{tag}}\n      - if loop.last && !users.is_empty()\n        td last\n  - else\n    tr\n      td No users\nol\n  - for (i, n) in (1..=limit).enumerate()\n    li {{i}}:{{n}}"
+    )]
+    struct Users {
+        users: Vec<User>,
+        limit: u32,
+    }
+    let user = |name: &str, tags: Vec<&'static str>| User {
+        name: name.into(),
+        tags,
+    };
+    let users = Users {
+        users: vec![
+            user("<Neo>", vec!["one", "chosen"]),
+            user("Trinity", vec![]),
+        ],
+        limit: 2,
+    };
+    assert_eq!(
+        users.render(),
+        "<table><tr class=\"row-1\"><td>&lt;Neo&gt;</td><td>one</td><td>,</td><td>chosen</td></tr>\
+         <tr class=\"row-2\"><td>Trinity</td><td>last</td></tr></table><ol><li>0:1</li><li>1:2</li></ol>"
+    );
+    let empty = Users {
+        users: vec![],
+        limit: 0,
+    };
+    assert_eq!(
+        empty.render(),
+        "<table><tr><td>No users</td></tr></table><ol></ol>"
+    );
+    let context = slimr::Context::new().with("users", vec!["Neo", "Trinity"]);
+    let html = slimr::render_template_str(
+        "ul\n  - for user in users\n    li {{loop.index}}. {{user}}\n  - else\n    li None",
+        context,
+    )
+    .unwrap();
+    assert_eq!(html, "<ul><li>1. Neo</li><li>2. Trinity</li></ul>");
+}
+
+#[test]
+fn renders_matches() {
+    #[allow(dead_code)]
+    enum Status {
+        Active,
+        Banned { reason: String },
+        Away(u32),
+    }
+    #[derive(SlimR)]
+    #[template(
+        source = "- match status\n  - when Status::Active\n    p Active\n  - when Status::Banned { reason }\n    p Banned: {{reason}}\n  - when Status::Away(days) if *days > limit\n    p Gone\n  - when Status::Away(days)\n    p Away for {{days}} days\n- if let Some(name) = nickname\n  p {{name}}\n- else if let Some(first) = names.first()\n  p {{first}}\n- else\n  p Anonymous"
+    )]
+    struct Profile {
+        status: Status,
+        limit: u32,
+        nickname: Option<String>,
+        names: Vec<&'static str>,
+    }
+    let profile = |status, nickname: Option<&str>, names| Profile {
+        status,
+        limit: 30,
+        nickname: nickname.map(Into::into),
+        names,
+    };
+    assert_eq!(
+        profile(Status::Active, Some("<Neo>"), vec![]).render(),
+        "<p>Active</p><p>&lt;Neo&gt;</p>"
+    );
+    assert_eq!(
+        profile(
+            Status::Banned {
+                reason: "spam".into()
+            },
+            None,
+            vec!["Thomas"]
+        )
+        .render(),
+        "<p>Banned: spam</p><p>Thomas</p>"
+    );
+    assert_eq!(
+        profile(Status::Away(3), None, vec![]).render(),
+        "<p>Away for 3 days</p><p>Anonymous</p>"
+    );
+    assert_eq!(
+        profile(Status::Away(40), None, vec![]).render(),
+        "<p>Gone</p><p>Anonymous</p>"
+    );
+}
+
+fn money(cents: &u32, currency: &str) -> String {
+    format!("{}.{:02} {}", cents / 100, cents % 100, currency)
+}
+
+#[test]
+fn renders_filters() {
+    #[derive(SlimR)]
+    #[template(
+        source = "p {{ name | trim | upper }}: {{ bio | truncate(5) }}\np {{ nickname | default(\"Anon\") }} {{ tags | join(\", \") }} ({{ tags | length }})\na href=\"/search?q={{ query | urlencode }}\" data-tags={{ tags | json }} {{ price | money(\"EUR\") }}"
+    )]
+    struct Profile<'a> {
+        name: &'a str,
+        bio: String,
+        nickname: Option<&'a str>,
+        tags: Vec<&'a str>,
+        query: &'a str,
+        price: u32,
+    }
+    let p = Profile {
+        name: " neo ",
+        bio: "The One <3".into(),
+        nickname: None,
+        tags: vec!["one", "chosen"],
+        query: "red pill",
+        price: 1999,
+    };
+    assert_eq!(
+        p.render(),
+        "<p>NEO: The O...</p><p>Anon one, chosen (2)</p>\
+         <a href=\"/search?q=red%20pill\" data-tags=\"[&quot;one&quot;,&quot;chosen&quot;]\">19.99 EUR</a>"
+    );
+}
+
+#[test]
+fn renders_any_rust_expression() {
+    #[derive(SlimR)]
+    #[template(
+        source = "p {{ n as u32 }} {{ -n }} {{ names.iter().map(|n| n.len()).sum::<usize>() }}"
+    )]
+    struct Page {
+        n: i8,
+        names: Vec<&'static str>,
+    }
+    let p = Page {
+        n: 7,
+        names: vec!["Neo", "Trinity"],
+    };
+    assert_eq!(p.render(), "<p>7 -7 10</p>");
+}
+
+#[test]
+fn fails_on_values_json_can_not_serialize() {
+    #[derive(SlimR)]
+    #[template(source = "p {{ scores | json }}")]
+    struct Page {
+        scores: std::collections::BTreeMap<Vec<u8>, u8>,
+    }
+    let p = Page {
+        scores: [(vec![1], 2)].into(),
+    };
+    let mut html = String::new();
+    assert!(p.render_into(&mut html).is_err());
+    assert!(p.try_render().is_err());
+    let panic = std::panic::catch_unwind(|| p.render()).unwrap_err();
+    assert_eq!(
+        panic.downcast_ref::<String>().unwrap(),
+        "a value of the template couldn't be formatted: Error"
+    );
+}
+
+#[test]
+fn passes_functions_to_methods() {
+    fn shout(item: &&str) -> String {
+        item.to_uppercase()
+    }
+    #[derive(SlimR)]
+    #[template(source = "p {{ items.iter().map(shout).count() }}")]
+    struct List<'a> {
+        items: Vec<&'a str>,
+    }
+    let l = List {
+        items: vec!["a", "b"],
+    };
+    assert_eq!(l.render(), "<p>2</p>");
+}
+
+#[test]
+fn renders_macros() {
+    #[derive(SlimR)]
+    #[template(source = "p {{ format!(\"{}!\", name) }} {{ matches!(count, 1 | 2) }}")]
+    struct Greeting<'a> {
+        name: &'a str,
+        count: u8,
+    }
+    let g = Greeting {
+        name: "Neo",
+        count: 2,
+    };
+    assert_eq!(g.render(), "<p>Neo! true</p>");
+}
+
+#[test]
+fn renders_expressions() {
+    struct User {
+        name: String,
+        roles: Vec<&'static str>,
+    }
+    #[derive(SlimR)]
+    #[template(
+        source = "p {{user.name}} has {{ user.roles.len() }} roles, {{ user.roles[0] | upper }} first\np {{ scores[\"neo\"] * 2 + 1 }} {{ user.name.starts_with(\"N\") }}"
+    )]
+    struct Page {
+        user: User,
+        scores: std::collections::HashMap<String, u32>,
+        #[allow(dead_code)]
+        unused: std::cell::Cell<u8>,
+    }
+    let p = Page {
+        user: User {
+            name: "Neo".into(),
+            roles: vec!["admin", "user"],
+        },
+        scores: [("neo".to_string(), 20)].into(),
+        unused: std::cell::Cell::new(0),
+    };
+    assert_eq!(
+        p.render(),
+        "<p>Neo has 2 roles, ADMIN first</p><p>41 true</p>"
+    );
+}
+
+#[test]
+fn checks_unused_fields() {
+    #[derive(SlimR)]
+    #[template(source = "- for tag in tags\n  b {{ tag }}{{ sep }}", warn_unused)]
+    struct Tags<'a> {
+        tags: Vec<&'a str>,
+        sep: char,
+    }
+    let t = Tags {
+        tags: vec!["a", "b"],
+        sep: ';',
+    };
+    assert_eq!(t.render(), "<b>a;</b><b>b;</b>");
+}
+
+#[test]
+fn renders_tuple_and_unit_structs() {
+    #[derive(SlimR)]
+    #[template(source = "p {{0}} has {{ 1.len() }} tags\n- for tag in 1\n  b {{tag}}")]
+    struct Tagged<'a>(&'a str, Vec<&'a str>);
+    #[derive(SlimR)]
+    #[template(source = "h1 Not found")]
+    struct NotFound;
+    assert_eq!(
+        Tagged("Neo", vec!["one"]).render(),
+        "<p>Neo has 1 tags</p><b>one</b>"
+    );
+    assert_eq!(NotFound.render(), "<h1>Not found</h1>");
+}
+
+#[test]
+fn renders_enum_variants() {
+    #[derive(SlimR)]
+    enum Page<'a> {
+        #[template(source = "p Loading")]
+        Loading,
+        #[template(
+            source = "- for item in items\n  li {{item}}\np {{ items.len() + 1 }} {{out}}",
+            warn_unused
+        )]
+        Loaded { items: Vec<&'a str>, out: u8 },
+        #[template(source = "p.error {{0}}")]
+        Failed(String, #[allow(dead_code)] u16),
+    }
+    assert_eq!(Page::Loading.render(), "<p>Loading</p>");
+    assert_eq!(
+        Page::Loaded {
+            items: vec!["a"],
+            out: 0
+        }
+        .render(),
+        "<li>a</li><p>2 0</p>"
+    );
+    assert_eq!(
+        Page::Failed("<oops>".into(), 500).render(),
+        "<p class=\"error\">&lt;oops&gt;</p>"
+    );
+}